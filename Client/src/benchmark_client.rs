@@ -1,39 +1,142 @@
-use tokio::sync::mpsc::Sender;
-use log::{info, warn};
-use rand::Rng;
-use tokio::time::{interval, Duration, Instant};
-use smallbank::SmallBankTransactionHandler;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::{Sender, UnboundedReceiver};
+use log::{debug, info, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::time::{interval, sleep, timeout, Duration, Instant};
+use smallbank::{ExecutionOutcome, SmallBankTransactionHandler, MAX_TX_LEN};
 use anyhow::Result;
-use crate::coordinator::Transaction;
+use crate::coordinator::{client_tx_uid, Transaction, TxUid};
+use crate::outcome::{OutcomeTracker, TxOutcome};
+use crate::retry::RetryPolicy;
 
 pub struct Client {
     size: usize,
     sb_handler: SmallBankTransactionHandler,
     rate: u64,
+    /// Identifies the client in the uids of its transactions.
+    client_id: u64,
+    /// Seeds the uids of the transactions.
+    seed: u64,
+    /// The transactions committed by the coordinator, applied to the balances of the handler when it
+    /// tracks committed balances.
+    rx_committed: Option<UnboundedReceiver<Transaction>>,
+    /// The outcomes reported by the coordinator and the transactions waiting for theirs, if the client
+    /// tracks them.
+    outcomes: Option<(UnboundedReceiver<(TxUid, TxOutcome)>, OutcomeTracker)>,
 }
 
 impl Client {
-    pub fn new(size: usize, sb_handler: SmallBankTransactionHandler, rate: u64) -> Self {
+    pub fn new(
+        size: usize,
+        sb_handler: SmallBankTransactionHandler,
+        rate: u64,
+        client_id: u64,
+        seed: u64,
+        rx_committed: Option<UnboundedReceiver<Transaction>>,
+    ) -> Self {
         Client {
             size,
             sb_handler,
             rate,
+            client_id,
+            seed,
+            rx_committed,
+            outcomes: None,
         }
     }
 
-    pub async fn send(&self, tx_coordinator: Sender<Transaction>) -> Result<()> {
+    /// Follow every transaction until the coordinator reports its outcome on `rx_outcomes`, or until it
+    /// times out after `outcome_timeout` ms, and send aborted transactions again according to
+    /// `retry_policy`. The summary of the outcomes is logged at shutdown.
+    pub fn set_outcome_tracking(
+        &mut self,
+        rx_outcomes: UnboundedReceiver<(TxUid, TxOutcome)>,
+        outcome_timeout: u64,
+        retry_policy: RetryPolicy,
+    ) {
+        let timeout = Duration::from_millis(outcome_timeout);
+        let tracker = OutcomeTracker::with_retries(timeout, retry_policy, self.seed);
+        self.outcomes = Some((rx_outcomes, tracker));
+    }
+
+    /// Send again the aborted transactions whose backoff elapsed. Retries are sent at the start of the
+    /// next burst, so their backoff is rounded up to the burst duration.
+    async fn send_retries(&mut self, tx_coordinator: &Sender<Transaction>) -> Result<(), SendError<Transaction>> {
+        if let Some((_, tracker)) = self.outcomes.as_mut() {
+            for transaction in tracker.due_retries() {
+                tx_coordinator.send(transaction).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the outcomes reported since the last burst, and time out the transactions waiting for too
+    /// long.
+    fn track_outcomes(&mut self) {
+        if let Some((rx_outcomes, tracker)) = self.outcomes.as_mut() {
+            while let Ok((tx_uid, outcome)) = rx_outcomes.try_recv() {
+                if let Some(completion) = tracker.complete(tx_uid, outcome) {
+                    debug!("Transaction {} {:?} after {} ms", tx_uid, outcome, completion.latency.as_millis());
+                }
+            }
+            for completion in tracker.expire() {
+                debug!("Transaction {} timed out", completion.tx_uid);
+            }
+        }
+    }
+
+    /// Wait for the outcome of the transactions in flight (or for them to time out), then log the
+    /// summary of the outcomes.
+    async fn finish_tracking(&mut self, tx_coordinator: &Sender<Transaction>) {
+        while self.outcomes.as_ref().is_some_and(|(_, tracker)| tracker.pending() > 0) {
+            if let Err(e) = self.send_retries(tx_coordinator).await {
+                warn!("Failed to send transaction to coordinator: {}", e);
+            }
+            if let Some((rx_outcomes, tracker)) = self.outcomes.as_mut() {
+                match timeout(Duration::from_millis(10), rx_outcomes.recv()).await {
+                    Ok(Some((tx_uid, outcome))) => {
+                        tracker.complete(tx_uid, outcome);
+                    }
+                    Ok(None) => sleep(Duration::from_millis(10)).await,
+                    Err(_) => (),
+                }
+                tracker.expire();
+            }
+        }
+        if let Some((_, tracker)) = self.outcomes.as_ref() {
+            info!("Transaction outcomes: {}", tracker.stats());
+        }
+    }
+
+    /// Apply the transactions committed since the last burst, so that the next transactions are drawn
+    /// against up-to-date balances.
+    fn apply_committed(&mut self) {
+        if let Some(rx_committed) = self.rx_committed.as_mut() {
+            while let Ok(transaction) = rx_committed.try_recv() {
+                match self.sb_handler.execute_transaction(transaction.into()) {
+                    Ok(ExecutionOutcome::Committed) => (),
+                    Ok(outcome) => debug!("Committed transaction had no effect: {:?}", outcome),
+                    Err(e) => warn!("Failed to apply committed transaction: {}", e),
+                }
+            }
+        }
+    }
+
+    pub async fn send(&mut self, tx_coordinator: Sender<Transaction>) -> Result<()> {
         const PRECISION: u64 = 20; // Sample precision.
         const BURST_DURATION: u64 = 1000 / PRECISION;
 
-        if self.size < 9 {
-            return Err(anyhow::Error::msg(
-                "Transaction size must be at least 9 bytes",
+        if self.size < MAX_TX_LEN {
+            return Err(anyhow::anyhow!(
+                "Transaction size must be at least {} bytes",
+                MAX_TX_LEN
             ));
         }
 
         let burst = self.rate / PRECISION;
         let mut counter = 0;
-        let mut r = rand::thread_rng().gen();
+        let mut r: u64 = StdRng::seed_from_u64(self.seed).gen();
         let interval = interval(Duration::from_millis(BURST_DURATION));
         const MAX_TRANSACTIONS: u64 = 1000;
         let mut total_sent = 0;
@@ -48,24 +151,45 @@ impl Client {
             }
             interval.as_mut().tick().await;
             let now = Instant::now();
+            self.apply_committed();
+            self.track_outcomes();
+            if let Err(e) = self.send_retries(&tx_coordinator).await {
+                warn!("Failed to send transaction to coordinator: {}", e);
+                break 'main;
+            }
             
             let mut x : u64 = 0;
             while x <= burst {
-                let tx_uid;
-
-                if x == counter % burst {
-                    tx_uid = counter;
+                let tx_uid = if x == counter % burst {
                     //info!("Sending sample transaction {}", tx_uid);
+                    client_tx_uid(self.client_id, counter)
                 } else {
-                    r += 1;
-                    tx_uid = r;
-                }
+                    r = r.wrapping_add(1);
+                    client_tx_uid(self.client_id, r)
+                };
                 let bytes = self.sb_handler.get_next_transaction(x == counter % burst, tx_uid);
                 
-                if let Err(e) = tx_coordinator.send(bytes.to_vec()).await {
+                let transaction = bytes.to_vec();
+                if let Err(e) = tx_coordinator.send(transaction.clone()).await {
                     warn!("Failed to send transaction to coordinator: {}", e);
                     break 'main;
                 }
+                if let Some((_, tracker)) = self.outcomes.as_mut() {
+                    if x == counter % burst {
+                        // Report the end-to-end latency of the sample transactions.
+                        let completion = tracker.completion(tx_uid, transaction);
+                        tokio::spawn(async move {
+                            if let Ok(completion) = completion.await {
+                                info!(
+                                    "Sample transaction {} {:?} in {} ms after {} attempt(s)",
+                                    completion.tx_uid, completion.outcome, completion.latency.as_millis(), completion.attempts
+                                );
+                            }
+                        });
+                    } else {
+                        tracker.register(tx_uid, transaction);
+                    }
+                }
                 //info!("Sent transaction {} to coordinator", tx_uid);
 
                 x += 1;
@@ -76,6 +200,7 @@ impl Client {
             counter += 1;
             total_sent += 1;
         }
+        self.finish_tracking(&tx_coordinator).await;
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use smallbank::UserId;
+use std::fs;
+use std::net::SocketAddr;
+
+#[cfg(test)]
+#[path = "tests/config_tests.rs"]
+pub mod config_tests;
+
+/// The membership of a single shard.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    /// The first user of the shard, when users are placed by contiguous ranges.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<UserId>,
+    /// The preferred replica of the shard.
+    pub leader: SocketAddr,
+    /// The addresses of all the replicas of the shard (including the leader).
+    pub replicas: Vec<SocketAddr>,
+}
+
+impl Shard {
+    /// The replicas of the shard, leader first.
+    pub fn addresses(&self) -> Vec<SocketAddr> {
+        let mut addresses = vec![self.leader];
+        addresses.extend(self.replicas.iter().filter(|x| **x != self.leader));
+        addresses
+    }
+}
+
+/// Describes the shards of the system and their replicas. Shard `i` is the i-th entry of `shards`.
+/// The same file is meant to be shared by the client and the nodes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Membership {
+    pub shards: Vec<Shard>,
+}
+
+impl Membership {
+    /// Assign the nodes to `num_shards` shards in a round-robin fashion, following the layout of the
+    /// benchmark scripts (which list the workers of each node in order, the i-th worker serving shard
+    /// i). The first replica of each shard is its leader.
+    pub fn round_robin(nodes: &[SocketAddr], num_shards: u32) -> Result<Self> {
+        ensure!(num_shards > 0, "There must be at least one shard");
+        ensure!(
+            nodes.len() >= num_shards as usize,
+            "Expected at least one node per shard ({} nodes for {} shards)",
+            nodes.len(),
+            num_shards
+        );
+        let shards = (0..num_shards as usize)
+            .map(|i| {
+                let replicas: Vec<_> = nodes.iter().skip(i).step_by(num_shards as usize).copied().collect();
+                Shard {
+                    start: None,
+                    leader: replicas[0],
+                    replicas,
+                }
+            })
+            .collect();
+        Ok(Self { shards })
+    }
+
+    /// Read a membership file.
+    pub fn import(path: &str) -> Result<Self> {
+        let data = fs::read(path).context(format!("Failed to read membership file {}", path))?;
+        let membership: Self = serde_json::from_slice(&data)
+            .context(format!("Failed to parse membership file {}", path))?;
+        membership.validate()?;
+        Ok(membership)
+    }
+
+    pub fn num_shards(&self) -> u32 {
+        self.shards.len() as u32
+    }
+
+    /// The first user of each shard, if the membership specifies all of them.
+    pub fn starts(&self) -> Option<Vec<UserId>> {
+        self.shards.iter().map(|shard| shard.start).collect()
+    }
+
+    /// The replicas of each shard, leader first.
+    pub fn addresses(&self) -> Vec<Vec<SocketAddr>> {
+        self.shards.iter().map(|shard| shard.addresses()).collect()
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(!self.shards.is_empty(), "The membership must contain at least one shard");
+        for (i, shard) in self.shards.iter().enumerate() {
+            ensure!(
+                shard.replicas.contains(&shard.leader),
+                "The leader of shard {} is not one of its replicas",
+                i
+            );
+        }
+        if let Some(starts) = self.starts() {
+            ensure!(
+                starts[0] == 0 && starts.windows(2).all(|w| w[0] < w[1]),
+                "Shard ranges must start at user 0 and be sorted"
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,119 +1,549 @@
-use anyhow::{Context, Result};
-use log::{info, warn, error};
-use smallbank::SmallBankTransactionHandler;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc::{channel, Receiver, Sender};
-use std::fs::OpenOptions;
-use std::io::Write;
+use crate::config::Membership;
+use crate::decision_log::{DecisionLog, InFlight, LogRecord};
+use crate::lock_manager::{LockManager, LockMode};
+use crate::messages::{CoordinatorMessage, QueryReply, Vote};
+use crate::outcome::{AbortReason, TxOutcome};
+use crate::replication::{Replicator, HEARTBEAT_INTERVAL};
+use anyhow::Result;
+use bytes::Bytes;
+use futures::future::join_all;
+use futures::stream::futures_unordered::FuturesUnordered;
+use futures::stream::StreamExt as _;
+use log::{debug, info, warn};
+use network::{CancelHandler, ReliableSender};
+use serde::{Deserialize, Serialize};
+use smallbank::{DecodeError, ShardId, ShardMap, SmallBankTransactionHandler, UserId};
+use std::collections::{BTreeSet, HashMap};
 use std::net::SocketAddr;
-use tokio::net::TcpStream;
+use std::sync::Arc;
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
+use tokio::time::{interval, sleep, timeout, Duration};
 
+#[cfg(test)]
+#[path = "tests/coordinator_tests.rs"]
+pub mod coordinator_tests;
+
+/// How long (in ms) the coordinator waits for the votes of all participants before aborting.
+const VOTE_TIMEOUT: u64 = 5_000;
 
 pub type Transaction = Vec<u8>;
-type UserId = u64;
-type ShardId = u32;
+pub type TxUid = u64;
+
+/// The number of low bits of a transaction uid numbering the transactions of a client. The high bits
+/// hold the id of the client, so that the transactions of different clients never share a uid at the
+/// participants.
+pub const CLIENT_UID_BITS: u32 = 48;
+/// The maximum number of clients sharing the participants.
+pub const MAX_CLIENTS: u64 = 1 << (64 - CLIENT_UID_BITS);
+
+/// The uid of the transaction numbered `index` by the client `client_id` (only the low
+/// `CLIENT_UID_BITS` bits of `index` are kept).
+pub fn client_tx_uid(client_id: u64, index: u64) -> TxUid {
+    (client_id << CLIENT_UID_BITS) | (index & ((1 << CLIENT_UID_BITS) - 1))
+}
+
+/// What the coordinator does once the record of a step of a transaction is durably logged.
+enum Logged {
+    /// Send the prepare to the participants.
+    Prepare(TxUid),
+    /// Deliver the decision to the participants and report the outcome.
+    Decision(TxUid, Decision, TxOutcome),
+    /// Nothing: the transaction is finished.
+    Done,
+}
+
+/// How the coordinator serves read-only transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadMode {
+    /// Reads go through the two-phase commit like every other transaction.
+    Ordered,
+    /// Reads query one replica of the shard of the user (spreading the queries over the replicas),
+    /// without being ordered: they may miss recently committed transactions.
+    Replica,
+    /// Reads query the leader of the shard of the user, which answers once it confirmed its
+    /// leadership.
+    Linearizable,
+}
 
+/// The outcome of the voting phase of the two-phase commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Decision {
+    Commit,
+    Abort,
+}
+
+/// Drives the two-phase commit of every transaction received from the client. The coordinator sends
+/// a PREPARE to every replica of every shard touched by the transaction, and commits only if all of
+/// them vote yes. Transactions are processed concurrently: the votes of a transaction are awaited in the
+/// background while the coordinator keeps preparing the next ones. Transactions conflicting with
+/// in-flight ones wait for their locks (up to `lock_timeout` ms) before being prepared.
 pub struct Coordinator {
     rx_transaction: Receiver<Transaction>,
-    nodes: Vec<SocketAddr>,
-    locks: Arc<Mutex<HashMap<UserId, bool>>>,
-    num_shards: u32,
+    /// The addresses of the replicas of each shard, leader first.
+    nodes: Vec<Vec<SocketAddr>>,
+    /// Shared locks for the read set and exclusive locks for the write set of in-flight transactions.
+    locks: LockManager,
+    /// The maximum time (in ms) a transaction waits for its locks before being dropped.
+    lock_timeout: u64,
+    /// Transactions holding or waiting for their locks, the users they touch, and their attempt.
+    pending: HashMap<TxUid, (Transaction, Vec<UserId>, u64)>,
+    /// The number of transactions admitted so far, telling apart the attempts of a retried transaction
+    /// (which reuse its uid).
+    attempts: u64,
+    /// Decides which shard owns each user.
+    shard_map: Arc<dyn ShardMap>,
     sb_handler: SmallBankTransactionHandler,
+    /// Receives the committed transactions, if the client tracks the balances they produce.
+    tx_committed: Option<UnboundedSender<Transaction>>,
+    read_mode: ReadMode,
+    /// Receives the replies to the balance queries, if any.
+    tx_balances: Option<UnboundedSender<(TxUid, QueryReply)>>,
+    /// Receives the outcome of every transaction of the client, if it tracks them.
+    tx_outcomes: Option<UnboundedSender<(TxUid, TxOutcome)>>,
+    /// The replica of each shard serving the next query, in `ReadMode::Replica`.
+    next_replica: Vec<usize>,
+    /// Durably records the prepares and decisions, if the coordinator must survive crashes.
+    decision_log: Option<DecisionLog>,
+    /// Ships the decision log to the other coordinators of the group, if the coordinator is replicated.
+    replicator: Option<Replicator>,
+    /// The transactions left in flight by a previous run, finished when the coordinator starts.
+    recovered: Vec<InFlight>,
+    /// Reliable sender used to talk to the participant shards.
+    network: ReliableSender,
 }
 
 impl Coordinator {
+    /// Create a new coordinator. The SmallBank handler is only used to parse and validate
+    /// transactions.
     pub fn new(
         rx_transaction: Receiver<Transaction>,
-        nodes: Vec<SocketAddr>,
-        num_shards: u32,
-        size: usize,
-        n_users: u64,
-        skew_factor: f64,
-        prob_choose_mtx: f64,
+        membership: &Membership,
+        shard_map: Arc<dyn ShardMap>,
+        lock_timeout: u64,
+        sb_handler: SmallBankTransactionHandler,
+        tx_committed: Option<UnboundedSender<Transaction>>,
     ) -> Self {
-        let sb_handler = SmallBankTransactionHandler::new(size, n_users, skew_factor, prob_choose_mtx);
-
         Coordinator {
             rx_transaction,
-            nodes,
-            locks: Arc::new(Mutex::new(HashMap::new())),
-            num_shards,
+            nodes: membership.addresses(),
+            locks: LockManager::new(),
+            lock_timeout,
+            pending: HashMap::new(),
+            attempts: 0,
+            shard_map,
             sb_handler,
+            tx_committed,
+            read_mode: ReadMode::Ordered,
+            tx_balances: None,
+            tx_outcomes: None,
+            next_replica: vec![0; membership.num_shards() as usize],
+            decision_log: None,
+            replicator: None,
+            recovered: Vec::new(),
+            network: ReliableSender::new(),
         }
     }
 
-    async fn ping_nodes(&self) -> Result<()> {
-        for (i, node) in self.nodes.iter().enumerate() {
-            match TcpStream::connect(node).await {
-                Ok(_) => {
-                    info!("Node {} at address {} is available", i, node);
-                }
-                Err(e) => {
-                    error!("Failed to connect to node {} at address {}: {}", i, node, e);
-                }
+    /// Record every prepare and decision in `decision_log` before acting on it, and finish the
+    /// transactions a previous run left in flight: undecided transactions abort, and decided ones
+    /// are delivered again to their participants.
+    pub fn set_decision_log(&mut self, decision_log: DecisionLog, in_flight: Vec<InFlight>) {
+        self.decision_log = Some(decision_log);
+        self.recover(in_flight);
+    }
+
+    /// Replicate every prepare and decision on the other coordinators of the group before acting on
+    /// it, so that they can take over if this coordinator crashes.
+    pub fn set_replication(&mut self, replicator: Replicator) {
+        self.replicator = Some(replicator);
+    }
+
+    /// Finish the specified in-flight transactions when the coordinator starts. A transaction known
+    /// from several sources (like the decision log and the coordinator group) is finished once, with
+    /// the decision any of them knows.
+    pub fn recover(&mut self, in_flight: Vec<InFlight>) {
+        for tx in in_flight {
+            match self.recovered.iter_mut().find(|x| x.tx_uid == tx.tx_uid) {
+                Some(known) => known.decision = known.decision.or(tx.decision),
+                None => self.recovered.push(tx),
             }
         }
-        Ok(())
+    }
+
+    /// Serve read-only transactions as balance queries instead of ordering them, and deliver the
+    /// replies to `tx_balances`.
+    pub fn set_read_mode(
+        &mut self,
+        read_mode: ReadMode,
+        tx_balances: Option<UnboundedSender<(TxUid, QueryReply)>>,
+    ) {
+        self.read_mode = read_mode;
+        self.tx_balances = tx_balances;
+    }
+
+    /// Report the outcome of every transaction received from the client to `tx_outcomes`: committed,
+    /// aborted (with the reason) or, for balance queries, timed out.
+    pub fn set_outcome_reporting(&mut self, tx_outcomes: UnboundedSender<(TxUid, TxOutcome)>) {
+        self.tx_outcomes = Some(tx_outcomes);
     }
 
     pub async fn run(&mut self) -> Result<()> {
         info!("Coordinator started");
 
-        self.ping_nodes().await?;
-        
-        let mut shard_logs = HashMap::new();
-        for shard_id in 0..self.num_shards {
-            let file = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(format!("shard_{}_transactions.log", shard_id))
-                .context(format!("Failed to open log file for shard {}", shard_id))?;
-            shard_logs.insert(shard_id, file);
+        // Transactions waiting for the votes of their participants.
+        let mut voting = FuturesUnordered::new();
+        // Handlers of the COMMIT and ABORT messages not yet acknowledged by the participants.
+        let mut pending_acks = FuturesUnordered::new();
+        // Timers bounding the time transactions wait for their locks.
+        let mut lock_timers = FuturesUnordered::new();
+        // Transactions just received, admitted after the select.
+        let mut incoming = Vec::new();
+        // Transactions that just acquired all their locks.
+        let mut ready = Vec::new();
+        // Decided transactions whose delivery is not logged as done yet, and the retry held back until
+        // it is, so that the records of two attempts of a transaction do not interleave in the log.
+        let mut finishing: HashMap<TxUid, Option<Transaction>> = HashMap::new();
+        // Balance queries waiting for their reply.
+        let mut queries = FuturesUnordered::new();
+        // Steps waiting for their record to be replicated on a majority of the group.
+        let mut logging = FuturesUnordered::new();
+        // Tells the followers the coordinator is alive, if it is replicated.
+        let mut heartbeat = interval(Duration::from_millis(HEARTBEAT_INTERVAL));
+        // Whether the client stopped sending transactions.
+        let mut input_closed = false;
+
+        for tx in std::mem::take(&mut self.recovered) {
+            let users = match self.parse_transaction(&tx.transaction) {
+                Ok((_, _, users)) => users,
+                Err(e) => {
+                    warn!(
+                        "Dropping malformed recovered transaction {}: {}",
+                        tx.tx_uid, e
+                    );
+                    continue;
+                }
+            };
+            let decision = match tx.decision {
+                Some(decision) => decision,
+                None => {
+                    let record = LogRecord::Decision(tx.tx_uid, Decision::Abort);
+                    self.log(record, Logged::Done).await?.await?;
+                    Decision::Abort
+                }
+            };
+            info!("Recovered transaction {}: {:?}", tx.tx_uid, decision);
+            finishing.insert(tx.tx_uid, None);
+            pending_acks.push(self.deliver_decision(tx.tx_uid, decision, &users).await);
         }
 
-        while let Some(transaction) = self.rx_transaction.recv().await {
-            let user_id = self.extract_user_id(&transaction);
-            let shard_id = self.get_shard_id(user_id);
+        loop {
+            // The heartbeat is always ready, so stop explicitly once every transaction is finished.
+            if input_closed
+                && self.pending.is_empty()
+                && voting.is_empty()
+                && pending_acks.is_empty()
+                && queries.is_empty()
+                && logging.is_empty()
+            {
+                break;
+            }
+
+            tokio::select! {
+                transaction = self.rx_transaction.recv(), if !input_closed => match transaction {
+                    Some(transaction) => incoming.push(transaction),
+                    None => input_closed = true,
+                },
+                Some((tx_uid, outcome)) = voting.next() => {
+                    // Phase two: notify every involved shard of the decision.
+                    let decision = match outcome {
+                        TxOutcome::Committed => Decision::Commit,
+                        _ => Decision::Abort,
+                    };
+                    debug!("Transaction {} decided: {:?}", tx_uid, outcome);
+                    let record = LogRecord::Decision(tx_uid, decision);
+                    logging.push(self.log(record, Logged::Decision(tx_uid, decision, outcome)).await?);
+                },
+                Some(step) = logging.next() => match step? {
+                    Logged::Prepare(tx_uid) => {
+                        let (transaction, users, _) = &self.pending[&tx_uid];
+                        let participants = self.get_participants(users);
+                        let message = CoordinatorMessage::Prepare(tx_uid, transaction.clone());
+                        let handlers = self.broadcast(&participants, &message).await;
+                        voting.push(Self::wait_for_votes(tx_uid, handlers));
+                    }
+                    Logged::Decision(tx_uid, decision, outcome) => {
+                        finishing.insert(tx_uid, None);
+                        let (transaction, users, _) = self.pending.remove(&tx_uid).expect("Decided transaction is not pending");
+                        pending_acks.push(self.deliver_decision(tx_uid, decision, &users).await);
+                        if let (Decision::Commit, Some(tx_committed)) = (decision, &self.tx_committed) {
+                            if tx_committed.send(transaction).is_err() {
+                                debug!("Dropping committed transaction {}: the client is gone", tx_uid);
+                            }
+                        }
+                        self.report(tx_uid, outcome);
+                        ready.extend(self.locks.release(tx_uid));
+                    }
+                    Logged::Done => (),
+                },
+                Some((tx_uid, attempt)) = lock_timers.next() => {
+                    // The timer is stale if the transaction got its locks in time, or if it belongs to
+                    // an earlier attempt of a retried transaction.
+                    let current = self.pending.get(&tx_uid).map(|(_, _, attempt)| *attempt);
+                    if current != Some(attempt) || !self.locks.is_waiting(tx_uid) {
+                        continue;
+                    }
+                    warn!("Dropping transaction {}: timed out waiting for locks", tx_uid);
+                    self.pending.remove(&tx_uid);
+                    self.report(tx_uid, TxOutcome::Aborted(AbortReason::LockTimeout));
+                    ready.extend(self.locks.release(tx_uid));
+                },
+                Some((tx_uid, reply)) = queries.next() => {
+                    match reply {
+                        Some(reply) => {
+                            debug!("Query {} answered: {:?}", tx_uid, reply);
+                            self.report(tx_uid, TxOutcome::Committed);
+                            if let Some(tx_balances) = &self.tx_balances {
+                                if tx_balances.send((tx_uid, reply)).is_err() {
+                                    debug!("Dropping the reply to query {}: nobody is listening", tx_uid);
+                                }
+                            }
+                        }
+                        None => {
+                            warn!("Query {} failed: no valid reply in time", tx_uid);
+                            self.report(tx_uid, TxOutcome::TimedOut);
+                        }
+                    }
+                },
+                _ = heartbeat.tick(), if self.replicator.is_some() => {
+                    if let Some(replicator) = self.replicator.as_mut() {
+                        replicator.heartbeat().await;
+                    }
+                },
+                Some(tx_uid) = pending_acks.next() => {
+                    // The decision has been delivered: the transaction will not be recovered.
+                    logging.push(self.log(LogRecord::Done(tx_uid), Logged::Done).await?);
+                    if let Some(Some(transaction)) = finishing.remove(&tx_uid) {
+                        incoming.push(transaction);
+                    }
+                },
+                else => break,
+            }
 
-            if self.acquire_locks(&transaction).await {
-                if let Some(file) = shard_logs.get_mut(&shard_id) {
-                    if let Err(e) = writeln!(file, "{}", String::from_utf8_lossy(&transaction)) {
-                        error!("Failed to write transaction to shard {} log: {}", shard_id, e);
-                    } else {
-                        //info!("Transaction written to shard {} log", shard_id);
+            // Phase one: ask every shard involved in a transaction holding its locks to prepare it.
+            for transaction in incoming.drain(..) {
+                let (tx_uid, access, users) = match self.parse_transaction(&transaction) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        warn!("Dropping malformed transaction: {}", e);
+                        let bytes = Bytes::from(transaction);
+                        if let Ok(tx_uid) = self.sb_handler.get_transaction_uid(bytes) {
+                            self.report(tx_uid, TxOutcome::Aborted(AbortReason::Invalid));
+                        }
+                        continue;
                     }
+                };
+                if access == 'r' && self.read_mode != ReadMode::Ordered {
+                    // Reads bypass the two-phase commit, and thus the locks.
+                    queries.push(self.query(tx_uid, users[0]).await);
+                    continue;
                 }
-                self.release_locks(&transaction).await;
-            } else {
-                warn!("Failed to acquire locks for transaction");
+                let held = finishing.get(&tx_uid);
+                if self.pending.contains_key(&tx_uid) || held.is_some_and(Option::is_some) {
+                    warn!(
+                        "Dropping transaction {}: a transaction with the same uid is in flight",
+                        tx_uid
+                    );
+                    self.report(tx_uid, TxOutcome::Aborted(AbortReason::Duplicate));
+                    continue;
+                }
+                if held.is_some() {
+                    // A retry waits until the previous attempt is done.
+                    finishing.insert(tx_uid, Some(transaction));
+                    continue;
+                }
+                let granted = self.acquire_locks(tx_uid, access, &users);
+                let attempt = self.attempts;
+                self.attempts += 1;
+                self.pending.insert(tx_uid, (transaction, users, attempt));
+                if granted {
+                    ready.push(tx_uid);
+                } else {
+                    let delay = self.lock_timeout;
+                    lock_timers.push(async move {
+                        sleep(Duration::from_millis(delay)).await;
+                        (tx_uid, attempt)
+                    });
+                }
+            }
+
+            // The prepare is sent once it is logged.
+            for tx_uid in ready.drain(..) {
+                let record = LogRecord::Prepare(tx_uid, self.pending[&tx_uid].0.clone());
+                logging.push(self.log(record, Logged::Prepare(tx_uid)).await?);
             }
         }
         Ok(())
     }
 
-    fn extract_user_id(&self, transaction: &Transaction) -> UserId {
-        self.sb_handler.get_transaction_uid(transaction.clone().into())
+    /// Collect the votes of the participants of a transaction and decide its outcome. The transaction
+    /// aborts if any participant votes no, sends an invalid reply, or does not reply in time.
+    async fn wait_for_votes(tx_uid: TxUid, handlers: Vec<CancelHandler>) -> (TxUid, TxOutcome) {
+        let outcome = match timeout(Duration::from_millis(VOTE_TIMEOUT), join_all(handlers)).await {
+            Ok(replies) => {
+                let votes: Vec<_> = replies
+                    .into_iter()
+                    .map(|reply| bincode::deserialize::<Vote>(&reply.ok()?).ok())
+                    .collect();
+                if votes.contains(&Some(Vote::No)) {
+                    TxOutcome::Aborted(AbortReason::VotedNo)
+                } else if votes.contains(&None) {
+                    TxOutcome::Aborted(AbortReason::NoVote)
+                } else {
+                    TxOutcome::Committed
+                }
+            }
+            Err(_) => TxOutcome::Aborted(AbortReason::NoVote),
+        };
+        (tx_uid, outcome)
     }
 
-    fn get_shard_id(&self, user_id: UserId) -> ShardId {
-        user_id as u32 % self.num_shards
+    /// Report the outcome of a transaction to the client, if it tracks them.
+    fn report(&self, tx_uid: TxUid, outcome: TxOutcome) {
+        if let Some(tx_outcomes) = &self.tx_outcomes {
+            if tx_outcomes.send((tx_uid, outcome)).is_err() {
+                debug!(
+                    "Dropping the outcome of transaction {}: the client is gone",
+                    tx_uid
+                );
+            }
+        }
+    }
+
+    /// Send the decision on a transaction to its participants, and return a future resolving once
+    /// all of them acknowledged it.
+    async fn deliver_decision(
+        &mut self,
+        tx_uid: TxUid,
+        decision: Decision,
+        users: &[UserId],
+    ) -> impl std::future::Future<Output = TxUid> {
+        let message = match decision {
+            Decision::Commit => CoordinatorMessage::Commit(tx_uid),
+            Decision::Abort => CoordinatorMessage::Abort(tx_uid),
+        };
+        let participants = self.get_participants(users);
+        let handlers = self.broadcast(&participants, &message).await;
+        async move {
+            join_all(handlers).await;
+            tx_uid
+        }
+    }
+
+    /// Durably record a step of a transaction in the decision log and start replicating it on the
+    /// group, if the coordinator keeps a log or is replicated. Return a future resolving to `step` once
+    /// a majority of the group holds the record, so that replication does not block the coordinator.
+    async fn log(
+        &mut self,
+        record: LogRecord,
+        step: Logged,
+    ) -> Result<impl std::future::Future<Output = Result<Logged>>> {
+        if let Some(decision_log) = self.decision_log.as_mut() {
+            decision_log.append(&record)?;
+        }
+        let replicated = match self.replicator.as_mut() {
+            Some(replicator) => Some(replicator.replicate(record).await),
+            None => None,
+        };
+        Ok(async move {
+            if let Some(replicated) = replicated {
+                replicated.await?;
+            }
+            Ok(step)
+        })
     }
 
-    async fn acquire_locks(&self, transaction: &Transaction) -> bool {
-        let mut locks = self.locks.lock().unwrap();
-        let user_id = self.extract_user_id(transaction);
-        
-        if *locks.get(&user_id).unwrap_or(&false){
-            return false;
+    /// Send a balance query for a user to the replica selected by the read mode, and return a future
+    /// resolving to its reply (`None` if the replica sends an invalid reply or does not reply in time).
+    async fn query(
+        &mut self,
+        tx_uid: TxUid,
+        user: UserId,
+    ) -> impl std::future::Future<Output = (TxUid, Option<QueryReply>)> {
+        let shard_id = self.get_shard_id(user) as usize;
+        let replicas = &self.nodes[shard_id];
+        let linearizable = self.read_mode == ReadMode::Linearizable;
+        let address = match linearizable {
+            true => replicas[0],
+            false => {
+                let replica = self.next_replica[shard_id];
+                self.next_replica[shard_id] = (replica + 1) % replicas.len();
+                replicas[replica]
+            }
+        };
+        let message = CoordinatorMessage::Query {
+            tx_uid,
+            user,
+            linearizable,
+        };
+        let bytes = bincode::serialize(&message).expect("Failed to serialize coordinator message");
+        let handler = self.network.send(address, Bytes::from(bytes)).await;
+        async move {
+            let reply = match timeout(Duration::from_millis(VOTE_TIMEOUT), handler).await {
+                Ok(Ok(bytes)) => bincode::deserialize(&bytes).ok(),
+                _ => None,
+            };
+            (tx_uid, reply)
         }
-        locks.insert(user_id, true);
-        true
     }
 
-    async fn release_locks(&self, transaction: &Transaction) {
-        let mut locks = self.locks.lock().unwrap();
-        let user_id = self.extract_user_id(transaction);
-        locks.insert(user_id, false);
+    /// Reliably send a message to every replica of the specified shards.
+    async fn broadcast(
+        &mut self,
+        shards: &[ShardId],
+        message: &CoordinatorMessage,
+    ) -> Vec<CancelHandler> {
+        let bytes = bincode::serialize(message).expect("Failed to serialize coordinator message");
+        let addresses = shards
+            .iter()
+            .flat_map(|shard_id| self.nodes[*shard_id as usize].iter().copied())
+            .collect();
+        self.network.broadcast(addresses, Bytes::from(bytes)).await
+    }
+
+    /// Return the uid of a transaction, its access type (`'r'` or `'w'`) and the users it touches.
+    fn parse_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<(TxUid, char, Vec<UserId>), DecodeError> {
+        let bytes = Bytes::from(transaction.clone());
+        let tx_uid = self.sb_handler.get_transaction_uid(bytes.clone())?;
+        let (access, users) = self.sb_handler.get_transaction_dependency(bytes)?;
+        Ok((
+            tx_uid,
+            access,
+            users.into_iter().map(UserId::from).collect(),
+        ))
     }
-}
\ No newline at end of file
+
+    /// Compute the (sorted and deduplicated) set of shards owning the specified users.
+    fn get_participants(&self, users: &[UserId]) -> Vec<ShardId> {
+        users
+            .iter()
+            .map(|user_id| self.get_shard_id(*user_id))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    fn get_shard_id(&self, user_id: UserId) -> ShardId {
+        self.shard_map.shard(user_id)
+    }
+
+    /// Request a lock on every user touched by the transaction: shared locks if the transaction only
+    /// reads, exclusive locks otherwise. Returns true if all the locks are immediately granted.
+    fn acquire_locks(&mut self, tx_uid: TxUid, access: char, users: &[UserId]) -> bool {
+        let mode = LockMode::from_access(access);
+        let requests: Vec<_> = users.iter().map(|user_id| (*user_id, mode)).collect();
+        self.locks.acquire(tx_uid, &requests)
+    }
+}
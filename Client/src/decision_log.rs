@@ -0,0 +1,156 @@
+use crate::coordinator::{Decision, Transaction, TxUid};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+#[path = "tests/decision_log_tests.rs"]
+pub mod decision_log_tests;
+
+/// A record of the decision log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogRecord {
+    /// The coordinator is about to ask the participants to prepare the transaction.
+    Prepare(TxUid, Transaction),
+    /// The coordinator decided the outcome of the transaction: this is the commit point.
+    Decision(TxUid, Decision),
+    /// Every participant acknowledged the decision.
+    Done(TxUid),
+}
+
+/// A transaction that was not done when the coordinator stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InFlight {
+    pub tx_uid: TxUid,
+    pub transaction: Transaction,
+    /// The decision, if the coordinator reached it before stopping.
+    pub decision: Option<Decision>,
+}
+
+/// An append-only file recording the progress of the two-phase commits of the coordinator, so that
+/// a restarted coordinator can finish the transactions it left in flight. Every record is synced to
+/// disk before `append` returns. Records are length-prefixed, and a record torn by a crash is ignored.
+pub struct DecisionLog {
+    file: File,
+}
+
+impl DecisionLog {
+    /// Open the log at `path` (creating it if needed) and return the transactions left in flight. The
+    /// log is compacted: only the records of the in-flight transactions are kept.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<(Self, Vec<InFlight>)> {
+        let path = path.as_ref();
+        let records = match fs::read(path) {
+            Ok(bytes) => Self::parse(&bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(e).context(format!("Failed to read decision log {}", path.display()))
+            }
+        };
+        let in_flight = Self::in_flight(records);
+
+        // Rewrite the log with the in-flight transactions only, then atomically replace the old one.
+        let mut compacted = PathBuf::from(path);
+        compacted.set_extension("compact");
+        let mut log = Self::create(&compacted)?;
+        for tx in &in_flight {
+            log.append(&LogRecord::Prepare(tx.tx_uid, tx.transaction.clone()))?;
+            if let Some(decision) = tx.decision {
+                log.append(&LogRecord::Decision(tx.tx_uid, decision))?;
+            }
+        }
+        fs::rename(&compacted, path)
+            .context(format!("Failed to replace decision log {}", path.display()))?;
+        Ok((log, in_flight))
+    }
+
+    fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .context(format!("Failed to create decision log {}", path.display()))?;
+        Ok(Self { file })
+    }
+
+    /// Durably append a record to the log.
+    pub fn append(&mut self, record: &LogRecord) -> Result<()> {
+        let bytes = bincode::serialize(record).expect("Failed to serialize log record");
+        let mut entry = (bytes.len() as u32).to_be_bytes().to_vec();
+        entry.extend_from_slice(&bytes);
+        self.file
+            .write_all(&entry)
+            .context("Failed to append to the decision log")?;
+        self.file
+            .sync_data()
+            .context("Failed to sync the decision log")?;
+        Ok(())
+    }
+
+    /// Parse the records of a log, stopping at the first incomplete or corrupted one.
+    pub(crate) fn parse(bytes: &[u8]) -> Vec<LogRecord> {
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while let Some(prefix) = bytes.get(offset..offset + 4) {
+            let len = u32::from_be_bytes(prefix.try_into().unwrap()) as usize;
+            let record = match bytes.get(offset + 4..offset + 4 + len) {
+                Some(record) => record,
+                None => break,
+            };
+            match bincode::deserialize(record) {
+                Ok(record) => records.push(record),
+                Err(_) => break,
+            }
+            offset += 4 + len;
+        }
+        records
+    }
+
+    /// Replay the records of a log and return the transactions that are not done, by uid.
+    fn in_flight(records: Vec<LogRecord>) -> Vec<InFlight> {
+        let mut tracker = InFlightTracker::default();
+        for record in records {
+            tracker.apply(record);
+        }
+        tracker.in_flight()
+    }
+}
+
+/// Follows the records of a decision log to know the transactions in flight.
+#[derive(Debug, Default)]
+pub struct InFlightTracker {
+    in_flight: BTreeMap<TxUid, InFlight>,
+}
+
+impl InFlightTracker {
+    pub fn apply(&mut self, record: LogRecord) {
+        match record {
+            LogRecord::Prepare(tx_uid, transaction) => {
+                self.in_flight.insert(
+                    tx_uid,
+                    InFlight {
+                        tx_uid,
+                        transaction,
+                        decision: None,
+                    },
+                );
+            }
+            LogRecord::Decision(tx_uid, decision) => {
+                if let Some(tx) = self.in_flight.get_mut(&tx_uid) {
+                    tx.decision = Some(decision);
+                }
+            }
+            LogRecord::Done(tx_uid) => {
+                self.in_flight.remove(&tx_uid);
+            }
+        }
+    }
+
+    /// The transactions in flight, by uid.
+    pub fn in_flight(&self) -> Vec<InFlight> {
+        self.in_flight.values().cloned().collect()
+    }
+}
@@ -0,0 +1,178 @@
+use crate::coordinator::TxUid;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+#[cfg(test)]
+#[path = "tests/lock_manager_tests.rs"]
+pub mod lock_manager_tests;
+
+/// The key on which locks are taken: the id of a SmallBank user.
+pub type Key = u64;
+
+/// Reads take shared locks and writes take exclusive locks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+impl LockMode {
+    /// Convert the access type returned by `SmallBankTransactionHandler::get_transaction_dependency`.
+    pub fn from_access(access: char) -> Self {
+        match access {
+            'r' => LockMode::Shared,
+            _ => LockMode::Exclusive,
+        }
+    }
+}
+
+/// The current state of the lock of a single key.
+struct Lock {
+    mode: LockMode,
+    holders: HashSet<TxUid>,
+    /// Transactions waiting for this key, served in FIFO order.
+    queue: VecDeque<(TxUid, LockMode)>,
+}
+
+impl Lock {
+    fn new(mode: LockMode) -> Self {
+        Self {
+            mode,
+            holders: HashSet::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Check whether a lock in the specified mode can be granted, ignoring the wait queue.
+    fn is_compatible(&self, mode: LockMode) -> bool {
+        self.holders.is_empty() || (self.mode == LockMode::Shared && mode == LockMode::Shared)
+    }
+
+    fn grant(&mut self, tx_uid: TxUid, mode: LockMode) {
+        if self.holders.is_empty() {
+            self.mode = mode;
+        }
+        self.holders.insert(tx_uid);
+    }
+}
+
+/// The locks requested by a transaction, sorted by key, and how many of them are already granted.
+struct Request {
+    keys: Vec<(Key, LockMode)>,
+    granted: usize,
+}
+
+/// A waiting lock table. Every transaction acquires its locks one key at a time following the global
+/// key order, and waits in the FIFO queue of the first key it cannot lock. Since no transaction ever
+/// waits for a key smaller than one it holds, there can be no cycle in the waits-for graph and thus
+/// no deadlock.
+#[derive(Default)]
+pub struct LockManager {
+    /// The lock of every key currently held or awaited by at least one transaction.
+    locks: HashMap<Key, Lock>,
+    /// The lock requests of every transaction holding or waiting for a lock.
+    requests: HashMap<TxUid, Request>,
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check whether the transaction is still waiting for some of its locks.
+    pub fn is_waiting(&self, tx_uid: TxUid) -> bool {
+        self.requests
+            .get(&tx_uid)
+            .is_some_and(|request| request.granted < request.keys.len())
+    }
+
+    /// Request the locks on all the specified keys. A key requested several times is locked with the
+    /// strongest mode. Returns true if the transaction immediately holds all its locks; otherwise the
+    /// transaction waits and is returned by `release` once its last lock is granted.
+    pub fn acquire(&mut self, tx_uid: TxUid, requests: &[(Key, LockMode)]) -> bool {
+        let mut keys = BTreeMap::new();
+        for (key, mode) in requests {
+            let entry = keys.entry(*key).or_insert(*mode);
+            *entry = (*entry).max(*mode);
+        }
+        let request = Request {
+            keys: keys.into_iter().collect(),
+            granted: 0,
+        };
+        self.requests.insert(tx_uid, request);
+        self.advance(tx_uid)
+    }
+
+    /// Release all the locks held by a transaction, or cancel it if it is still waiting (eg. because
+    /// it timed out). Returns the transactions that now hold all their locks, in the order in which
+    /// they were granted.
+    pub fn release(&mut self, tx_uid: TxUid) -> Vec<TxUid> {
+        let request = match self.requests.remove(&tx_uid) {
+            Some(request) => request,
+            None => return Vec::new(),
+        };
+
+        // The transaction holds the keys before `granted` and may wait in the queue of the next one.
+        let last = (request.granted + 1).min(request.keys.len());
+        let mut freed = Vec::new();
+        for (key, _) in &request.keys[..last] {
+            if let Some(lock) = self.locks.get_mut(key) {
+                lock.holders.remove(&tx_uid);
+                lock.queue.retain(|(waiting, _)| *waiting != tx_uid);
+                freed.push(*key);
+            }
+        }
+
+        let mut ready = Vec::new();
+        while let Some(key) = freed.pop() {
+            let lock = match self.locks.get_mut(&key) {
+                Some(lock) => lock,
+                None => continue,
+            };
+
+            // Grant the key to the longest waiting transactions, as long as they are compatible.
+            let mut woken = Vec::new();
+            while let Some((waiting, mode)) = lock.queue.front().copied() {
+                if !lock.is_compatible(mode) {
+                    break;
+                }
+                lock.queue.pop_front();
+                lock.grant(waiting, mode);
+                woken.push(waiting);
+            }
+            if lock.holders.is_empty() && lock.queue.is_empty() {
+                self.locks.remove(&key);
+            }
+
+            // Let the woken transactions try to lock their next keys.
+            for waiting in woken {
+                if let Some(request) = self.requests.get_mut(&waiting) {
+                    request.granted += 1;
+                }
+                if self.advance(waiting) {
+                    ready.push(waiting);
+                }
+            }
+        }
+        ready
+    }
+
+    /// Lock the next keys of a transaction until it holds all of them (returns true) or it has to
+    /// wait in the queue of a key (returns false).
+    fn advance(&mut self, tx_uid: TxUid) -> bool {
+        let request = match self.requests.get_mut(&tx_uid) {
+            Some(request) => request,
+            None => return false,
+        };
+        while let Some((key, mode)) = request.keys.get(request.granted).copied() {
+            let lock = self.locks.entry(key).or_insert_with(|| Lock::new(mode));
+            if lock.queue.is_empty() && lock.is_compatible(mode) {
+                lock.grant(tx_uid, mode);
+                request.granted += 1;
+            } else {
+                lock.queue.push_back((tx_uid, mode));
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -1,16 +1,38 @@
 mod benchmark_client;
+mod config;
 mod coordinator;
+mod decision_log;
+mod lock_manager;
+mod messages;
+mod outcome;
+mod replication;
+mod retry;
+mod sequencer;
 
 use crate::benchmark_client::Client;
-use crate::coordinator::Coordinator;
+use crate::config::Membership;
+use crate::coordinator::{Coordinator, ReadMode, MAX_CLIENTS};
+use crate::decision_log::DecisionLog;
+use crate::replication::{Follower, Replicator};
+use crate::retry::RetryPolicy;
+use crate::sequencer::{Sequencer, MAX_SEQUENCERS};
 
-use anyhow::{Context, Result};
-use tokio::sync::mpsc::channel;
-use smallbank::SmallBankTransactionHandler;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use tokio::sync::mpsc::{channel, unbounded_channel};
+use smallbank::{
+    BalanceTracking, ConsistentHashShardMap, LookupShardMap, ModuloShardMap, RangeShardMap, ShardMap,
+    SmallBankTransactionHandler, TransactionMix,
+};
 use clap::{crate_name, crate_version, App, AppSettings};
 use env_logger::Env;
-use std::net::SocketAddr;
 use log::info;
+use rand::rngs::StdRng;
+use rand::{Rng as _, SeedableRng as _};
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -26,10 +48,32 @@ async fn main() -> Result<()> {
         .args_from_usage("--size=<INT> 'The size of each transaction in bytes'")
         .args_from_usage("--n_users=<INT> 'Number of users in small-bank'")
         .args_from_usage("--skew_factor=<FLOAT> 'Skew factor for users in small-bank'")
-        .args_from_usage("--prob_choose_mtx=<FLOAT> 'Probability of choosing modifying transactions in small-bank'")
+        .args_from_usage("--prob_choose_mtx=[FLOAT] 'Probability of choosing modifying transactions in small-bank'")
+        .args_from_usage("--mix=[MIX] 'Transaction mix: a preset (oltpbench, uniform, read_only, write_only) or weights like deposit_saving=10,send=40,split=5 (replaces --prob_choose_mtx)'")
         .args_from_usage("--rate=<INT> 'The rate (txs/s) at which to send the transactions'")
-        .args_from_usage("--num_shards=<INT> 'Number of shards to use'")
-        .args_from_usage("--nodes=[ADDR]... 'Network addresses of nodes'")
+        .args_from_usage("--num_shards=[INT] 'Number of shards to use'")
+        .args_from_usage("--shard_map=[POLICY] 'Shard placement policy: modulo, range, consistent_hash or lookup'")
+        .args_from_usage("--shards=[VALUE]... 'Shard assignment of the benchmark scripts: <n_shards> <start>... <address>...'")
+        .args_from_usage("--shard_table=[FILE] 'Lookup table with one \"<user> <shard>\" pair per line'")
+        .args_from_usage("--lock_timeout=[INT] 'Maximum time (ms) a transaction waits for its locks'")
+        .args_from_usage("--nodes=[ADDR]... 'Network addresses of nodes (the i-th node is a replica of shard i % num_shards)'")
+        .args_from_usage("--membership=[FILE] 'Shard membership file (replaces --nodes)'")
+        .args_from_usage("--seed=[INT] 'Seed of the workload (random if not specified)'")
+        .args_from_usage("--client_id=[INT] 'Index of this client, to derive its own seed from --seed and keep its transaction uids unique'")
+        .args_from_usage("--track_balances=[MODE] 'Balances to draw amounts against: none, generated or committed'")
+        .args_from_usage("--cross_shard=[FLOAT] 'Probability that send and split transactions span several shards'")
+        .args_from_usage("--decision_log=[FILE] 'Log of the coordinator decisions, to finish in-flight transactions after a crash'")
+        .args_from_usage("--coordinators=[ADDR]... 'Addresses of a replicated coordinator group, the first one leading'")
+        .args_from_usage("--coordinator_rank=[INT] 'Rank of this process in the coordinator group (followers only coordinate after a failover)'")
+        .args_from_usage("--failover_timeout=[INT] 'Time (ms) a follower waits for a silent coordinator before taking over'")
+        .args_from_usage("--reads=[MODE] 'How reads are served: ordered (two-phase commit), replica or linearizable (shard leader)'")
+        .args_from_usage("--outcome_timeout=[INT] 'Time (ms) the client waits for the outcome of a transaction before counting it as timed out'")
+        .args_from_usage("--max_attempts=[INT] 'Maximum number of times an aborted transaction is sent (1 disables retries)'")
+        .args_from_usage("--retry_backoff=[INT] 'Backoff (ms) before the first retry, doubled at every retry'")
+        .args_from_usage("--max_retry_backoff=[INT] 'Maximum backoff (ms) between two attempts'")
+        .args_from_usage("--retry_on=[REASONS] 'Comma-separated abort reasons worth a retry: voted_no, no_vote, lock_timeout'")
+        .args_from_usage("--execution=[MODE] 'How cross-shard transactions execute: two_phase_commit or deterministic (sequenced in epochs, without locks or votes)'")
+        .args_from_usage("--batch_interval=[INT] 'Duration (ms) of the epochs of the sequencer, in deterministic execution'")
         .setting(AppSettings::ArgRequiredElseHelp)
         .get_matches();
 
@@ -37,44 +81,300 @@ async fn main() -> Result<()> {
     let size = matches.value_of("size").unwrap().parse::<usize>()?;
     let n_users = matches.value_of("n_users").unwrap().parse::<u64>()?;
     let skew_factor = matches.value_of("skew_factor").unwrap().parse::<f64>()?;
-    let prob_choose_mtx = matches.value_of("prob_choose_mtx").unwrap().parse::<f64>()?;
+    let mix = match (matches.value_of("mix"), matches.value_of("prob_choose_mtx")) {
+        (Some(mix), _) => mix.parse::<TransactionMix>()?,
+        (None, Some(prob_choose_mtx)) => {
+            let prob_choose_mtx = prob_choose_mtx.parse::<f64>()?;
+            ensure!(
+                (0.0..=1.0).contains(&prob_choose_mtx),
+                "The probability of modifying transactions must be between 0 and 1"
+            );
+            TransactionMix::from_prob_choose_mtx(prob_choose_mtx)
+        }
+        (None, None) => bail!("Missing --mix or --prob_choose_mtx"),
+    };
     let rate = matches.value_of("rate").unwrap().parse::<u64>()?;
     let num_shards = matches.value_of("num_shards").unwrap_or("4").parse::<u32>()?;
+    let lock_timeout = matches.value_of("lock_timeout").unwrap_or("1000").parse::<u64>()?;
+    let seed = match matches.value_of("seed") {
+        Some(seed) => seed.parse::<u64>()?,
+        None => rand::random(),
+    };
+    let client_id = matches.value_of("client_id").unwrap_or("0").parse::<u64>()?;
+    ensure!(client_id < MAX_CLIENTS, "The client id must be smaller than {}", MAX_CLIENTS);
+    let read_mode = match matches.value_of("reads").unwrap_or("ordered") {
+        "ordered" => ReadMode::Ordered,
+        "replica" => ReadMode::Replica,
+        "linearizable" => ReadMode::Linearizable,
+        mode => bail!("Unknown read mode '{}'", mode),
+    };
+
+    let outcome_timeout = matches.value_of("outcome_timeout").unwrap_or("10000").parse::<u64>()?;
+    let mut retry_policy = RetryPolicy::default();
+    if let Some(max_attempts) = matches.value_of("max_attempts") {
+        retry_policy.max_attempts = max_attempts.parse::<u32>()?;
+        ensure!(retry_policy.max_attempts > 0, "Transactions must be sent at least once");
+    }
+    if let Some(backoff) = matches.value_of("retry_backoff") {
+        retry_policy.base_backoff = Duration::from_millis(backoff.parse::<u64>()?);
+    }
+    if let Some(backoff) = matches.value_of("max_retry_backoff") {
+        retry_policy.max_backoff = Duration::from_millis(backoff.parse::<u64>()?);
+    }
+    if let Some(reasons) = matches.value_of("retry_on") {
+        retry_policy.retry_on = RetryPolicy::parse_reasons(reasons)?;
+    }
+    let deterministic = match matches.value_of("execution").unwrap_or("two_phase_commit") {
+        "two_phase_commit" => false,
+        "deterministic" => true,
+        mode => bail!("Unknown execution mode '{}'", mode),
+    };
+    let batch_interval = matches.value_of("batch_interval").unwrap_or("10").parse::<u64>()?;
+
+    // The `--shards` assignment (if any) provides the shard ranges.
+    let shards: Vec<&str> = matches.values_of("shards").map(|x| x.collect()).unwrap_or_default();
+    let starts = match shards.is_empty() {
+        true => None,
+        false => Some(parse_shard_starts(&shards)?),
+    };
 
-    let nodes: Vec<SocketAddr> = matches.values_of("nodes")
-        .unwrap()
-        .map(|addr| addr.parse().unwrap())
-        .collect();
+    // Load the membership of the shards, or build it from the list of nodes.
+    let membership = match matches.value_of("membership") {
+        Some(path) => Membership::import(path)?,
+        None => {
+            let nodes = matches
+                .values_of("nodes")
+                .ok_or_else(|| anyhow!("Missing --nodes or --membership"))?
+                .map(|addr| addr.parse::<SocketAddr>())
+                .collect::<Result<Vec<_>, _>>()?;
+            let num_shards = starts.as_ref().map_or(num_shards, |x| x.len() as u32);
+            Membership::round_robin(&nodes, num_shards)?
+        }
+    };
+    let shard_map = make_shard_map(
+        matches.value_of("shard_map").unwrap_or("modulo"),
+        membership.num_shards(),
+        n_users,
+        starts.or_else(|| membership.starts()),
+        matches.value_of("shard_table"),
+    )?;
+    ensure!(
+        shard_map.num_shards() == membership.num_shards(),
+        "The shard placement uses {} shards but the membership has {}",
+        shard_map.num_shards(),
+        membership.num_shards()
+    );
 
     // Create channel for communication
     let (tx_transaction, rx_transaction) = channel(1000);
 
     // Initialize SmallBankTransactionHandler
-    let sb_handler = SmallBankTransactionHandler::new(size, n_users, skew_factor, prob_choose_mtx);
+    let seed = client_seed(seed, client_id);
+    info!("Workload seed: {}", seed);
+    let mut sb_handler =
+        SmallBankTransactionHandler::with_seed(size, n_users, skew_factor, mix, seed);
+    let balance_tracking = match matches.value_of("track_balances").unwrap_or("none") {
+        "none" => BalanceTracking::None,
+        "generated" => BalanceTracking::Generated,
+        "committed" => BalanceTracking::Committed,
+        mode => bail!("Unknown balance tracking mode '{}'", mode),
+    };
+    sb_handler.set_balance_tracking(balance_tracking);
+    if let Some(prob_cross_shard) = matches.value_of("cross_shard") {
+        let prob_cross_shard = prob_cross_shard.parse::<f64>()?;
+        ensure!(
+            (0.0..=1.0).contains(&prob_cross_shard),
+            "The cross-shard probability must be between 0 and 1"
+        );
+        sb_handler.set_cross_shard_probability(shard_map.clone(), prob_cross_shard);
+    }
+
+    // Deterministic execution replaces the coordinator with a sequencer: the shards report the outcomes.
+    if deterministic {
+        ensure!(
+            balance_tracking != BalanceTracking::Committed && read_mode == ReadMode::Ordered,
+            "Deterministic execution does not support committed balance tracking nor unordered reads"
+        );
+        ensure!(
+            matches.value_of("decision_log").is_none() && !matches.is_present("coordinators"),
+            "Deterministic execution has no coordinator to log or replicate"
+        );
+        ensure!(
+            client_id < MAX_SEQUENCERS as u64,
+            "Deterministic execution supports client ids smaller than {}",
+            MAX_SEQUENCERS
+        );
+        let mut sequencer = Sequencer::new(
+            rx_transaction,
+            client_id as u32,
+            &membership,
+            shard_map,
+            batch_interval,
+            sb_handler.clone(),
+        )?;
+        let sequencer_handle = tokio::spawn(async move {
+            sequencer.run().await
+        });
+        let mut client = Client::new(size, sb_handler, rate, client_id, seed, None);
+        let client_handle = tokio::spawn(async move {
+            client.send(tx_transaction).await
+        });
+        let (sequencer_result, client_result) = tokio::try_join!(sequencer_handle, client_handle)?;
+        sequencer_result?;
+        client_result?;
+        return Ok(());
+    }
+
+    // Followers of a replicated coordinator only take over the in-flight transactions of the leader.
+    let coordinators = matches
+        .values_of("coordinators")
+        .map(|x| x.map(|addr| addr.parse::<SocketAddr>()).collect::<Result<Vec<_>, _>>())
+        .transpose()?
+        .unwrap_or_default();
+    let coordinator_rank = matches.value_of("coordinator_rank").unwrap_or("0").parse::<usize>()?;
+    let failover_timeout = matches.value_of("failover_timeout").unwrap_or("1000").parse::<u64>()?;
+    ensure!(
+        coordinator_rank == 0 || coordinator_rank < coordinators.len(),
+        "The coordinator rank must be smaller than the size of the coordinator group"
+    );
+    let decision_log = match matches.value_of("decision_log") {
+        Some(path) => {
+            let (decision_log, in_flight) = DecisionLog::open(path)?;
+            info!("Recovering {} in-flight transactions from {}", in_flight.len(), path);
+            Some((decision_log, in_flight))
+        }
+        None => None,
+    };
+    if coordinator_rank > 0 {
+        info!("Following the coordinator {}", coordinators[0]);
+        let follower = Follower::new(coordinator_rank, coordinators, failover_timeout);
+        return follower
+            .run(move |rx_transaction| {
+                // The client of the crashed leader is gone: nobody tracks the outcomes.
+                let mut coordinator =
+                    Coordinator::new(rx_transaction, &membership, shard_map, lock_timeout, sb_handler, None);
+                coordinator.set_read_mode(read_mode, None);
+                if let Some((decision_log, in_flight)) = decision_log {
+                    coordinator.set_decision_log(decision_log, in_flight);
+                }
+                coordinator
+            })
+            .await;
+    }
+
+    // The coordinator feeds the committed transactions back to the client, if it tracks them.
+    let (tx_committed, rx_committed) = match balance_tracking {
+        BalanceTracking::Committed => {
+            let (tx, rx) = unbounded_channel();
+            (Some(tx), Some(rx))
+        }
+        _ => (None, None),
+    };
 
     // Create and spawn coordinator
     let mut coordinator = Coordinator::new(
         rx_transaction,
-        nodes,
-        num_shards,
-        size,
-        n_users,
-        skew_factor,
-        prob_choose_mtx,
+        &membership,
+        shard_map,
+        lock_timeout,
+        sb_handler.clone(),
+        tx_committed,
     );
+    coordinator.set_read_mode(read_mode, None);
+    let (tx_outcomes, rx_outcomes) = unbounded_channel();
+    coordinator.set_outcome_reporting(tx_outcomes);
+    if coordinators.len() > 1 {
+        coordinator.set_replication(Replicator::new(0, coordinators[1..].to_vec(), coordinators.len()));
+    }
+    if let Some((decision_log, in_flight)) = decision_log {
+        coordinator.set_decision_log(decision_log, in_flight);
+    }
 
     let coordinator_handle = tokio::spawn(async move {
         coordinator.run().await
     });
 
     // Create and run client
-    let client = Client::new(size, sb_handler, rate);
+    let mut client = Client::new(size, sb_handler, rate, client_id, seed, rx_committed);
+    client.set_outcome_tracking(rx_outcomes, outcome_timeout, retry_policy);
     let client_handle = tokio::spawn(async move {
         client.send(tx_transaction).await
     });
 
     // Wait for both tasks to complete
-    tokio::try_join!(coordinator_handle, client_handle)?;
+    let (coordinator_result, client_result) = tokio::try_join!(coordinator_handle, client_handle)?;
+    coordinator_result?;
+    client_result?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Make the shard placement policy. The `range` policy uses the specified shard ranges when available,
+/// and splits the users evenly otherwise.
+fn make_shard_map(
+    policy: &str,
+    num_shards: u32,
+    n_users: u64,
+    starts: Option<Vec<u64>>,
+    shard_table: Option<&str>,
+) -> Result<Arc<dyn ShardMap>> {
+    let shard_map: Arc<dyn ShardMap> = match (policy, starts) {
+        ("modulo", _) => Arc::new(ModuloShardMap::new(num_shards)),
+        ("range", Some(starts)) => Arc::new(RangeShardMap::new(starts)),
+        ("range", None) => {
+            ensure!(
+                n_users >= num_shards as u64,
+                "Cannot split {} users into {} shard ranges",
+                n_users,
+                num_shards
+            );
+            Arc::new(RangeShardMap::uniform(n_users, num_shards))
+        }
+        ("consistent_hash", _) => Arc::new(ConsistentHashShardMap::new(num_shards)),
+        ("lookup", _) => {
+            let path = shard_table.ok_or_else(|| anyhow!("Missing --shard_table for lookup placement"))?;
+            Arc::new(LookupShardMap::new(read_shard_table(path, num_shards)?, num_shards))
+        }
+        _ => bail!("Unknown shard placement policy '{}'", policy),
+    };
+    Ok(shard_map)
+}
+
+/// Derive the seed of a client from the seed of the benchmark, so that clients generate different but
+/// reproducible workloads.
+fn client_seed(seed: u64, client_id: u64) -> u64 {
+    let mut rng = StdRng::seed_from_u64(seed);
+    std::iter::repeat_with(|| rng.gen()).nth(client_id as usize).unwrap()
+}
+
+/// Parse the first user of each shard out of a `--shards` assignment (ignoring the worker addresses
+/// at its end).
+fn parse_shard_starts(shards: &[&str]) -> Result<Vec<u64>> {
+    let n_shards = shards[0].parse::<usize>().context("Invalid number of shards")?;
+    ensure!(shards.len() > n_shards, "Expected the start of each of the {} shards", n_shards);
+    let starts = shards[1..=n_shards]
+        .iter()
+        .map(|x| x.parse::<u64>().context("Invalid shard start"))
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(
+        starts.first() == Some(&0) && starts.windows(2).all(|w| w[0] < w[1]),
+        "Shard ranges must start at user 0 and be sorted"
+    );
+    Ok(starts)
+}
+
+/// Read a lookup table with one `<user> <shard>` pair per line.
+fn read_shard_table(path: &str, num_shards: u32) -> Result<HashMap<u64, u32>> {
+    let content = fs::read_to_string(path).context(format!("Failed to read shard table {}", path))?;
+    let mut table = HashMap::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let mut fields = line.split_whitespace();
+        let (user, shard) = match (fields.next(), fields.next()) {
+            (Some(user), Some(shard)) => (user.parse::<u64>()?, shard.parse::<u32>()?),
+            _ => bail!("Invalid line in shard table: '{}'", line),
+        };
+        ensure!(shard < num_shards, "User {} is mapped to unknown shard {}", user, shard);
+        table.insert(user, shard);
+    }
+    Ok(table)
+}
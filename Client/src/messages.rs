@@ -0,0 +1,99 @@
+use crate::coordinator::{Transaction, TxUid};
+use crate::decision_log::{InFlight, LogRecord};
+use serde::{Deserialize, Serialize};
+use smallbank::{Sequence, UserId};
+
+/// Messages sent by the coordinator to the participant shards. Participants reply to a `Prepare`
+/// with a serialized `Vote` and acknowledge any other message with an arbitrary reply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoordinatorMessage {
+    /// Ask the participant to validate (and lock) its part of the transaction.
+    Prepare(TxUid, Transaction),
+    /// Tell the participant to apply a transaction it previously prepared.
+    Commit(TxUid),
+    /// Tell the participant to discard a transaction it previously prepared.
+    Abort(TxUid),
+    /// Ask a replica for the balances of a user, outside of the ordered log. A linearizable query is
+    /// only answered by the leader once it confirmed it is still the leader. Replicas reply with a
+    /// serialized `QueryReply`.
+    Query {
+        tx_uid: TxUid,
+        user: UserId,
+        linearizable: bool,
+    },
+}
+
+/// Messages sent by the sequencers to the shards when transactions execute deterministically instead
+/// of through two-phase commit. Shards acknowledge them with an arbitrary reply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SequencerMessage {
+    /// The transactions of an epoch of a sequencer that touch the shard, by increasing sequence number
+    /// (see `sequence_number`). Every sequencer sends a batch, possibly empty, to every shard in every
+    /// epoch until it closes: a shard executes the batches of an epoch once it received all of them
+    /// (from the sequencers still open), in sequence order.
+    Batch {
+        epoch: u64,
+        sequencer: u32,
+        transactions: Vec<(Sequence, Transaction)>,
+    },
+    /// The sequencer stopped: it sends no batch for `epoch` and the later epochs, so shards no longer
+    /// wait for it from then on.
+    Closed { epoch: u64, sequencer: u32 },
+}
+
+/// The reply of a participant shard to a `Prepare` message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Vote {
+    Yes,
+    No,
+}
+
+/// The reply of a replica to a `Query` message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueryReply {
+    Balance {
+        checking: u32,
+        saving: u32,
+    },
+    /// The replica does not hold the accounts of the user.
+    NotOwned,
+    /// The user does not exist.
+    UnknownUser,
+}
+
+/// The epoch of a coordinator group: epoch `e` is led by the coordinator of rank `e % group_size`.
+pub type Epoch = u64;
+
+/// Messages exchanged by the coordinators of a replicated group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicationMessage {
+    /// Append a record to the decision log of a follower, which replies with a `ReplicationReply`.
+    Append { epoch: Epoch, record: LogRecord },
+    /// Tell the followers the leader is alive (no reply).
+    Heartbeat { epoch: Epoch },
+    /// Ask a follower to stop following older epochs and to reply with the transactions it knows in
+    /// flight.
+    Takeover { epoch: Epoch },
+}
+
+impl ReplicationMessage {
+    pub fn epoch(&self) -> Epoch {
+        match self {
+            ReplicationMessage::Append { epoch, .. }
+            | ReplicationMessage::Heartbeat { epoch }
+            | ReplicationMessage::Takeover { epoch } => *epoch,
+        }
+    }
+}
+
+/// The reply of a follower to a `ReplicationMessage`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicationReply {
+    Ack,
+    /// The follower moved to a newer epoch.
+    Stale {
+        epoch: Epoch,
+    },
+    /// The transactions in flight according to the follower.
+    State(Vec<InFlight>),
+}
@@ -0,0 +1,326 @@
+use crate::coordinator::{Transaction, TxUid};
+use crate::retry::RetryPolicy;
+use rand::rngs::StdRng;
+use rand::SeedableRng as _;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use tokio::sync::oneshot;
+use tokio::time::{Duration, Instant};
+
+#[cfg(test)]
+#[path = "tests/outcome_tests.rs"]
+pub mod outcome_tests;
+
+/// Why the coordinator aborted (or dropped) a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AbortReason {
+    /// A participant voted no.
+    VotedNo,
+    /// A participant sent an invalid vote or did not vote in time.
+    NoVote,
+    /// The transaction timed out waiting for its locks.
+    LockTimeout,
+    /// The transaction failed validation.
+    Invalid,
+    /// A transaction with the same uid was in flight.
+    Duplicate,
+}
+
+impl fmt::Display for AbortReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            AbortReason::VotedNo => "voted no",
+            AbortReason::NoVote => "no vote",
+            AbortReason::LockTimeout => "lock timeout",
+            AbortReason::Invalid => "invalid",
+            AbortReason::Duplicate => "duplicate",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// What became of a transaction sent by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxOutcome {
+    /// The transaction committed (or the balance query was answered).
+    Committed,
+    Aborted(AbortReason),
+    /// The client heard nothing of the transaction in time.
+    TimedOut,
+}
+
+/// The final outcome of a transaction and the time it took, from the moment the client first sent it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Completion {
+    pub tx_uid: TxUid,
+    pub outcome: TxOutcome,
+    pub latency: Duration,
+    /// The number of times the transaction was sent.
+    pub attempts: u32,
+}
+
+/// Summary statistics over the completed transactions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutcomeStats {
+    pub committed: u64,
+    /// The transactions committed without any retry.
+    pub committed_first_attempt: u64,
+    pub aborted: BTreeMap<AbortReason, u64>,
+    pub timed_out: u64,
+    /// The transactions the coordinator dropped because they reused the uid of a transaction in
+    /// flight. They are not counted in the total: the transaction in flight keeps its own outcome.
+    pub duplicates: u64,
+    /// The number of times transactions were sent again.
+    pub retries: u64,
+    /// The latencies of the committed transactions.
+    latencies: Vec<Duration>,
+}
+
+impl OutcomeStats {
+    fn record(&mut self, completion: &Completion) {
+        match completion.outcome {
+            TxOutcome::Committed => {
+                self.committed += 1;
+                if completion.attempts == 1 {
+                    self.committed_first_attempt += 1;
+                }
+                self.latencies.push(completion.latency);
+            }
+            TxOutcome::Aborted(reason) => *self.aborted.entry(reason).or_insert(0) += 1,
+            TxOutcome::TimedOut => self.timed_out += 1,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.committed + self.aborted.values().sum::<u64>() + self.timed_out
+    }
+
+    /// The commit latency below which fall `percentile` percent of the committed transactions.
+    pub fn latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        let rank = (percentile / 100.0 * latencies.len() as f64).ceil() as usize;
+        latencies.get(rank.max(1) - 1).copied()
+    }
+
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let sum: Duration = self.latencies.iter().sum();
+        (!self.latencies.is_empty()).then(|| sum / self.latencies.len() as u32)
+    }
+}
+
+impl fmt::Display for OutcomeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let aborted: u64 = self.aborted.values().sum();
+        write!(
+            f,
+            "{} transactions: {} committed, {} aborted, {} timed out",
+            self.total(),
+            self.committed,
+            aborted,
+            self.timed_out
+        )?;
+        if aborted > 0 {
+            let reasons: Vec<_> = self
+                .aborted
+                .iter()
+                .map(|(reason, count)| format!("{}: {}", reason, count))
+                .collect();
+            write!(f, " ({})", reasons.join(", "))?;
+        }
+        write!(
+            f,
+            "; {} committed at the first attempt, {} retries",
+            self.committed_first_attempt, self.retries
+        )?;
+        if self.duplicates > 0 {
+            write!(f, ", {} duplicates dropped", self.duplicates)?;
+        }
+        if let (Some(mean), Some(p50), Some(p99), Some(max)) = (
+            self.mean_latency(),
+            self.latency_percentile(50.0),
+            self.latency_percentile(99.0),
+            self.latency_percentile(100.0),
+        ) {
+            write!(
+                f,
+                "; commit latency: mean {} ms, p50 {} ms, p99 {} ms, max {} ms",
+                mean.as_millis(),
+                p50.as_millis(),
+                p99.as_millis(),
+                max.as_millis()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A transaction waiting for its outcome.
+struct Pending {
+    transaction: Transaction,
+    /// When the transaction was first sent.
+    sent: Instant,
+    /// When the last attempt was sent.
+    attempt_sent: Instant,
+    attempts: u32,
+    /// When the transaction must be sent again, if its last attempt aborted.
+    retry_at: Option<Instant>,
+    sender: Option<oneshot::Sender<Completion>>,
+}
+
+/// Follows the transactions sent by the client until the coordinator reports their outcome, or until
+/// they time out. Transactions aborted for a transient reason are sent again according to the retry
+/// policy.
+pub struct OutcomeTracker {
+    /// How long the client waits for the outcome of an attempt.
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+    /// Draws the jitter of the backoffs.
+    rng: StdRng,
+    /// The transactions waiting for their outcome, by uid.
+    pending: HashMap<TxUid, Pending>,
+    stats: OutcomeStats,
+}
+
+impl OutcomeTracker {
+    /// Make a tracker retrying aborted transactions according to `retry_policy`, with backoffs
+    /// drawn from `seed`.
+    pub fn with_retries(timeout: Duration, retry_policy: RetryPolicy, seed: u64) -> Self {
+        Self {
+            timeout,
+            retry_policy,
+            rng: StdRng::seed_from_u64(seed),
+            pending: HashMap::new(),
+            stats: OutcomeStats::default(),
+        }
+    }
+
+    /// Start tracking a transaction that was just sent.
+    pub fn register(&mut self, tx_uid: TxUid, transaction: Transaction) {
+        self.track(tx_uid, transaction, None);
+    }
+
+    /// Start tracking a transaction that was just sent, and return a future resolving to its
+    /// completion.
+    pub fn completion(
+        &mut self,
+        tx_uid: TxUid,
+        transaction: Transaction,
+    ) -> oneshot::Receiver<Completion> {
+        let (sender, receiver) = oneshot::channel();
+        self.track(tx_uid, transaction, Some(sender));
+        receiver
+    }
+
+    /// Start tracking a transaction, unless a transaction with the same uid is already tracked: the
+    /// coordinator drops the second one, and the first one keeps its entry (the completion of the
+    /// second one never resolves).
+    fn track(
+        &mut self,
+        tx_uid: TxUid,
+        transaction: Transaction,
+        sender: Option<oneshot::Sender<Completion>>,
+    ) {
+        if self.pending.contains_key(&tx_uid) {
+            return;
+        }
+        let now = Instant::now();
+        let pending = Pending {
+            transaction,
+            sent: now,
+            attempt_sent: now,
+            attempts: 1,
+            retry_at: None,
+            sender,
+        };
+        self.pending.insert(tx_uid, pending);
+    }
+
+    /// Record the outcome of an attempt reported by the coordinator, and return the completion of the
+    /// transaction unless it will be sent again. Outcomes of transactions that are not tracked (or
+    /// already timed out, or waiting to be sent again) are ignored. A duplicate is only counted: the
+    /// transaction tracked under its uid is still in flight.
+    pub fn complete(&mut self, tx_uid: TxUid, outcome: TxOutcome) -> Option<Completion> {
+        if outcome == TxOutcome::Aborted(AbortReason::Duplicate) {
+            self.stats.duplicates += 1;
+            return None;
+        }
+        let pending = self.pending.get_mut(&tx_uid)?;
+        if pending.retry_at.is_some() {
+            return None;
+        }
+        if let TxOutcome::Aborted(reason) = outcome {
+            if self.retry_policy.should_retry(reason, pending.attempts) {
+                let backoff = self.retry_policy.backoff(pending.attempts, &mut self.rng);
+                pending.retry_at = Some(Instant::now() + backoff);
+                return None;
+            }
+        }
+        let pending = self.pending.remove(&tx_uid).unwrap();
+        Some(self.finish(tx_uid, pending, outcome))
+    }
+
+    /// Return the transactions whose backoff elapsed, to send them again.
+    pub fn due_retries(&mut self) -> Vec<Transaction> {
+        let now = Instant::now();
+        let mut due: Vec<_> = self
+            .pending
+            .iter_mut()
+            .filter(|(_, pending)| pending.retry_at.is_some_and(|x| x <= now))
+            .map(|(tx_uid, pending)| {
+                pending.retry_at = None;
+                pending.attempts += 1;
+                pending.attempt_sent = now;
+                (*tx_uid, pending.transaction.clone())
+            })
+            .collect();
+        self.stats.retries += due.len() as u64;
+        due.sort_by_key(|(tx_uid, _)| *tx_uid);
+        due.into_iter()
+            .map(|(_, transaction)| transaction)
+            .collect()
+    }
+
+    /// Time out the transactions whose last attempt has been waiting for longer than the timeout.
+    pub fn expire(&mut self) -> Vec<Completion> {
+        let expired: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| {
+                pending.retry_at.is_none() && pending.attempt_sent.elapsed() >= self.timeout
+            })
+            .map(|(tx_uid, _)| *tx_uid)
+            .collect();
+        expired
+            .into_iter()
+            .map(|tx_uid| {
+                let pending = self.pending.remove(&tx_uid).unwrap();
+                self.finish(tx_uid, pending, TxOutcome::TimedOut)
+            })
+            .collect()
+    }
+
+    fn finish(&mut self, tx_uid: TxUid, pending: Pending, outcome: TxOutcome) -> Completion {
+        let completion = Completion {
+            tx_uid,
+            outcome,
+            latency: pending.sent.elapsed(),
+            attempts: pending.attempts,
+        };
+        self.stats.record(&completion);
+        if let Some(sender) = pending.sender {
+            // The caller may have stopped waiting.
+            let _ = sender.send(completion);
+        }
+        completion
+    }
+
+    /// The number of transactions waiting for their outcome.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn stats(&self) -> &OutcomeStats {
+        &self.stats
+    }
+}
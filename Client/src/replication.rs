@@ -0,0 +1,301 @@
+use crate::coordinator::{Coordinator, Transaction};
+use crate::decision_log::{InFlight, InFlightTracker, LogRecord};
+use crate::messages::{Epoch, ReplicationMessage, ReplicationReply};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::lock::Mutex as AsyncMutex;
+use futures::sink::SinkExt as _;
+use futures::stream::futures_unordered::FuturesUnordered;
+use futures::stream::StreamExt as _;
+use log::{info, warn};
+use network::{MessageHandler, Receiver, ReliableSender, SimpleSender, Writer};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration, Instant};
+
+#[cfg(test)]
+#[path = "tests/replication_tests.rs"]
+pub mod replication_tests;
+
+/// How often (in ms) the leader of a coordinator group tells its followers it is alive.
+pub const HEARTBEAT_INTERVAL: u64 = 100;
+
+/// Ships the decision log of the leader of a coordinator group to its followers. A record is only
+/// acted upon once a majority of the group (counting the leader) holds it, so that any majority of
+/// followers knows every decision the leader may have delivered.
+pub struct Replicator {
+    epoch: Epoch,
+    followers: Vec<SocketAddr>,
+    /// The number of follower acknowledgements making a majority of the group.
+    quorum: usize,
+    network: ReliableSender,
+    heartbeats: SimpleSender,
+}
+
+impl Replicator {
+    /// Make the replicator of the leader of `epoch`, in a group of `group_size` coordinators.
+    pub fn new(epoch: Epoch, followers: Vec<SocketAddr>, group_size: usize) -> Self {
+        Self {
+            epoch,
+            followers,
+            quorum: group_size / 2,
+            network: ReliableSender::new(),
+            heartbeats: SimpleSender::new(),
+        }
+    }
+
+    /// Send a record to the followers, and return a future resolving once a majority of the group
+    /// holds it. The future fails if a follower moved to a newer epoch: another coordinator took over.
+    /// Records are delivered to every follower in the order they are replicated.
+    pub async fn replicate(&mut self, record: LogRecord) -> impl Future<Output = Result<()>> {
+        let (epoch, quorum) = (self.epoch, self.quorum);
+        let message = ReplicationMessage::Append { epoch, record };
+        let bytes = bincode::serialize(&message).expect("Failed to serialize replication message");
+        let mut replies: FuturesUnordered<_> = self
+            .network
+            .broadcast(self.followers.clone(), Bytes::from(bytes))
+            .await
+            .into_iter()
+            .collect();
+
+        async move {
+            let mut acks = 0;
+            while acks < quorum {
+                match replies.next().await {
+                    Some(Ok(reply)) => match bincode::deserialize(&reply) {
+                        Ok(ReplicationReply::Ack) => acks += 1,
+                        Ok(ReplicationReply::Stale { epoch: newer }) => {
+                            bail!("Coordinator of epoch {} replaced by epoch {}", epoch, newer)
+                        }
+                        _ => warn!("Invalid reply of a coordinator follower"),
+                    },
+                    Some(Err(_)) => (),
+                    None => bail!("Not enough coordinator followers to replicate the decision log"),
+                }
+            }
+
+            // Keep delivering the record to the slow followers (dropping their handlers would cancel it).
+            tokio::spawn(async move { while replies.next().await.is_some() {} });
+            Ok(())
+        }
+    }
+
+    /// Tell the followers the leader is alive.
+    pub async fn heartbeat(&mut self) {
+        let message = ReplicationMessage::Heartbeat { epoch: self.epoch };
+        let bytes = bincode::serialize(&message).expect("Failed to serialize replication message");
+        self.heartbeats
+            .broadcast(self.followers.clone(), Bytes::from(bytes))
+            .await;
+    }
+}
+
+/// What a follower knows of the group.
+struct FollowerState {
+    /// The newest epoch the follower heard of.
+    epoch: Epoch,
+    tracker: InFlightTracker,
+    /// When the follower last heard from the leader of its epoch.
+    last_heard: Instant,
+}
+
+/// Replicates the decision log of the leader of a coordinator group, and takes over the in-flight
+/// transactions when the leader falls silent. Followers take over in rank order after the leader:
+/// the follower `d` ranks after the leader waits `d` times the failover timeout.
+pub struct Follower {
+    rank: usize,
+    /// The addresses of the coordinators of the group, by rank.
+    group: Vec<SocketAddr>,
+    /// How long (in ms) the next follower waits for the silent leader before taking over.
+    failover_timeout: u64,
+    state: Arc<Mutex<FollowerState>>,
+    network: ReliableSender,
+}
+
+impl Follower {
+    pub fn new(rank: usize, group: Vec<SocketAddr>, failover_timeout: u64) -> Self {
+        let state = FollowerState {
+            epoch: 0,
+            tracker: InFlightTracker::default(),
+            last_heard: Instant::now(),
+        };
+        Self {
+            rank,
+            group,
+            failover_timeout,
+            state: Arc::new(Mutex::new(state)),
+            network: ReliableSender::new(),
+        }
+    }
+
+    /// Follow the leader until taking over. The new leader finishes the in-flight transactions with
+    /// the coordinator built by `make_coordinator`, and keeps leading the group.
+    pub async fn run<F>(mut self, make_coordinator: F) -> Result<()>
+    where
+        F: FnOnce(mpsc::Receiver<Transaction>) -> Coordinator,
+    {
+        let handler = FollowerHandler {
+            state: self.state.clone(),
+        };
+        Receiver::spawn(self.group[self.rank], handler);
+
+        let (epoch, in_flight) = loop {
+            sleep(Duration::from_millis(self.failover_timeout / 4 + 1)).await;
+            let (epoch, silence) = {
+                let state = self.state.lock().unwrap();
+                (state.epoch, state.last_heard.elapsed())
+            };
+            if silence < self.patience(epoch) {
+                continue;
+            }
+            if let Some(taken_over) = self.take_over().await {
+                break taken_over;
+            }
+        };
+
+        info!(
+            "Leading epoch {} with {} transactions in flight",
+            epoch,
+            in_flight.len()
+        );
+        // The clients of the crashed leader are gone: the new leader receives no transaction, but the
+        // input stays open so that it keeps leading the group.
+        let (_tx_transaction, rx_transaction) = mpsc::channel(1);
+        let mut coordinator = make_coordinator(rx_transaction);
+        coordinator.set_replication(Replicator::new(epoch, self.others(), self.group.len()));
+        coordinator.recover(in_flight);
+        coordinator.run().await
+    }
+
+    /// How long the follower waits for the silent leader of `epoch` before taking over.
+    fn patience(&self, epoch: Epoch) -> Duration {
+        let size = self.group.len();
+        let leader = (epoch % size as u64) as usize;
+        let distance = (self.rank + size - leader) % size;
+        Duration::from_millis(self.failover_timeout * distance as u64)
+    }
+
+    fn others(&self) -> Vec<SocketAddr> {
+        let mut others = self.group.clone();
+        others.remove(self.rank);
+        others
+    }
+
+    /// Move to a new epoch led by this follower, and gather the transactions in flight from a
+    /// majority of the group. Returns `None` if the group moved to a newer epoch or a majority does
+    /// not reply in time. The follower only adopts the new epoch once a majority replied, so that a
+    /// failed takeover does not fence a leader that is still alive.
+    async fn take_over(&mut self) -> Option<(Epoch, Vec<InFlight>)> {
+        let size = self.group.len() as u64;
+        let epoch = {
+            let mut state = self.state.lock().unwrap();
+            state.last_heard = Instant::now();
+            (state.epoch / size + 1) * size + self.rank as u64
+        };
+        info!("Taking over the coordinator group in epoch {}", epoch);
+
+        let message = ReplicationMessage::Takeover { epoch };
+        let bytes = bincode::serialize(&message).expect("Failed to serialize replication message");
+        let mut replies: FuturesUnordered<_> = self
+            .network
+            .broadcast(self.others(), Bytes::from(bytes))
+            .await
+            .into_iter()
+            .collect();
+
+        // The follower itself completes the majority.
+        let mut states = Vec::new();
+        let deadline = sleep(Duration::from_millis(self.failover_timeout));
+        tokio::pin!(deadline);
+        while states.len() < self.group.len() / 2 {
+            tokio::select! {
+                Some(reply) = replies.next() => match reply.map(|x| bincode::deserialize(&x)) {
+                    Ok(Ok(ReplicationReply::State(in_flight))) => states.push(in_flight),
+                    Ok(Ok(ReplicationReply::Stale { epoch })) => {
+                        info!("Giving up the takeover: the group moved to epoch {}", epoch);
+                        return None;
+                    }
+                    _ => warn!("Invalid reply of a coordinator follower"),
+                },
+                _ = &mut deadline => {
+                    warn!("Failed to take over: a majority of the group did not reply");
+                    return None;
+                },
+            }
+        }
+
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.epoch > epoch {
+                info!(
+                    "Giving up the takeover: the group moved to epoch {}",
+                    state.epoch
+                );
+                return None;
+            }
+            state.epoch = epoch;
+            states.push(state.tracker.in_flight());
+        }
+
+        // Every decision the old leader delivered is known by at least one member of the majority.
+        let mut merged: BTreeMap<_, InFlight> = BTreeMap::new();
+        for tx in states.into_iter().flatten() {
+            let entry = merged.entry(tx.tx_uid).or_insert_with(|| tx.clone());
+            entry.decision = entry.decision.or(tx.decision);
+        }
+        Some((epoch, merged.into_values().collect()))
+    }
+}
+
+#[derive(Clone)]
+struct FollowerHandler {
+    state: Arc<Mutex<FollowerState>>,
+}
+
+#[async_trait]
+impl MessageHandler for FollowerHandler {
+    async fn dispatch(
+        &self,
+        writer: Arc<AsyncMutex<Writer>>,
+        message: Bytes,
+    ) -> Result<(), Box<dyn Error>> {
+        let message: ReplicationMessage = bincode::deserialize(&message)?;
+        let reply = {
+            let mut state = self.state.lock().unwrap();
+            match message {
+                // Followers ignore the leaders of older epochs.
+                ReplicationMessage::Heartbeat { epoch } if epoch < state.epoch => None,
+                ReplicationMessage::Append { epoch, .. }
+                | ReplicationMessage::Takeover { epoch }
+                    if epoch < state.epoch =>
+                {
+                    Some(ReplicationReply::Stale { epoch: state.epoch })
+                }
+                message => {
+                    state.epoch = message.epoch();
+                    state.last_heard = Instant::now();
+                    match message {
+                        ReplicationMessage::Append { record, .. } => {
+                            state.tracker.apply(record);
+                            Some(ReplicationReply::Ack)
+                        }
+                        ReplicationMessage::Heartbeat { .. } => None,
+                        ReplicationMessage::Takeover { .. } => {
+                            Some(ReplicationReply::State(state.tracker.in_flight()))
+                        }
+                    }
+                }
+            }
+        };
+        if let Some(reply) = reply {
+            let bytes = bincode::serialize(&reply)?;
+            writer.lock().await.send(Bytes::from(bytes)).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use crate::outcome::AbortReason;
+use anyhow::{bail, Result};
+use rand::Rng;
+use tokio::time::Duration;
+
+#[cfg(test)]
+#[path = "tests/retry_tests.rs"]
+pub mod retry_tests;
+
+/// When and how often the client sends an aborted transaction again (with the same uid).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of times a transaction is sent: 1 disables retries.
+    pub max_attempts: u32,
+    /// The backoff before the first retry, doubled at every retry.
+    pub base_backoff: Duration,
+    /// The maximum backoff between two attempts.
+    pub max_backoff: Duration,
+    /// The abort reasons worth a retry: the ones that may not happen again.
+    pub retry_on: Vec<AbortReason>,
+}
+
+impl Default for RetryPolicy {
+    /// Transactions are never retried.
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            base_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(1_000),
+            retry_on: vec![
+                AbortReason::VotedNo,
+                AbortReason::NoVote,
+                AbortReason::LockTimeout,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Parse a comma-separated list of transient abort reasons, like `lock_timeout,no_vote`. Invalid
+    /// transactions are rejected again at every attempt, and retrying a duplicate would execute it
+    /// under the uid of another transaction, so they are never retried.
+    pub fn parse_reasons(reasons: &str) -> Result<Vec<AbortReason>> {
+        reasons
+            .split(',')
+            .map(|reason| match reason.trim() {
+                "voted_no" => Ok(AbortReason::VotedNo),
+                "no_vote" => Ok(AbortReason::NoVote),
+                "lock_timeout" => Ok(AbortReason::LockTimeout),
+                "invalid" => bail!("Invalid transactions cannot be retried"),
+                "duplicate" => bail!("Duplicate transactions cannot be retried"),
+                reason => bail!("Unknown abort reason '{}'", reason),
+            })
+            .collect()
+    }
+
+    /// Whether a transaction aborted for `reason` after `attempts` attempts is sent again.
+    pub fn should_retry(&self, reason: AbortReason, attempts: u32) -> bool {
+        attempts < self.max_attempts && self.retry_on.contains(&reason)
+    }
+
+    /// The time to wait before sending a transaction again after `attempts` attempts: exponential
+    /// backoff, of which a random half is skipped so that conflicting transactions do not retry in
+    /// lockstep.
+    pub fn backoff<R: Rng>(&self, attempts: u32, rng: &mut R) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempts.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let backoff = self
+            .base_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+            .as_millis() as u64;
+        Duration::from_millis(backoff / 2 + rng.gen_range(0..=backoff - backoff / 2))
+    }
+}
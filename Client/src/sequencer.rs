@@ -0,0 +1,173 @@
+use crate::config::Membership;
+use crate::coordinator::{Transaction, TxUid};
+use crate::messages::SequencerMessage;
+use anyhow::{ensure, Result};
+use bytes::Bytes;
+use futures::stream::futures_unordered::FuturesUnordered;
+use futures::stream::StreamExt as _;
+use log::{debug, info, warn};
+use network::{CancelHandler, ReliableSender};
+use smallbank::{Sequence, ShardId, ShardMap, SmallBankTransactionHandler};
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc::Receiver;
+use tokio::time::{interval, Duration};
+
+#[cfg(test)]
+#[path = "tests/sequencer_tests.rs"]
+pub mod sequencer_tests;
+
+/// The number of bits of a sequence number holding the position of a transaction in its batch.
+const INDEX_BITS: u32 = 20;
+/// The number of bits of a sequence number holding the sequencer.
+const SEQUENCER_BITS: u32 = 12;
+
+/// The maximum number of transactions of a batch: a full batch closes the epoch early.
+pub const MAX_BATCH_SIZE: usize = 1 << INDEX_BITS;
+/// The maximum number of sequencers.
+pub const MAX_SEQUENCERS: u32 = 1 << SEQUENCER_BITS;
+
+/// The position of a transaction in the global order: transactions are ordered by epoch, then by
+/// sequencer, then by position in the batch of their sequencer.
+pub fn sequence_number(epoch: u64, sequencer: u32, index: usize) -> Sequence {
+    (epoch << (SEQUENCER_BITS + INDEX_BITS)) | ((sequencer as u64) << INDEX_BITS) | index as u64
+}
+
+/// Orders the transactions of a client in epochs, as an alternative to the two-phase commit of the
+/// `Coordinator`. At the end of every epoch, the sequencer sends to every replica of every shard the
+/// transactions of the epoch touching the shard. The shards then execute every transaction in the
+/// global order, exchanging the balances that other participants need (see
+/// `smallbank::DeterministicShard`), so there are no votes, locks or aborts due to contention.
+pub struct Sequencer {
+    rx_transaction: Receiver<Transaction>,
+    /// The index of this sequencer among the sequencers of the system.
+    id: u32,
+    /// The addresses of the replicas of each shard, leader first.
+    nodes: Vec<Vec<SocketAddr>>,
+    /// Decides which shard owns each user.
+    shard_map: Arc<dyn ShardMap>,
+    sb_handler: SmallBankTransactionHandler,
+    /// The duration (in ms) of an epoch.
+    batch_interval: u64,
+    epoch: u64,
+    /// The transactions of the current epoch touching each shard.
+    batches: Vec<Vec<(Sequence, Transaction)>>,
+    /// The number of transactions sequenced in the current epoch.
+    batch_size: usize,
+    /// Reliable sender used to talk to the shards.
+    network: ReliableSender,
+}
+
+impl Sequencer {
+    /// Create a new sequencer. The SmallBank handler is only used to parse and validate transactions.
+    pub fn new(
+        rx_transaction: Receiver<Transaction>,
+        id: u32,
+        membership: &Membership,
+        shard_map: Arc<dyn ShardMap>,
+        batch_interval: u64,
+        sb_handler: SmallBankTransactionHandler,
+    ) -> Result<Self> {
+        ensure!(
+            id < MAX_SEQUENCERS,
+            "There can be at most {} sequencers",
+            MAX_SEQUENCERS
+        );
+        Ok(Sequencer {
+            rx_transaction,
+            id,
+            nodes: membership.addresses(),
+            shard_map,
+            sb_handler,
+            batch_interval,
+            epoch: 0,
+            batches: vec![Vec::new(); membership.num_shards() as usize],
+            batch_size: 0,
+            network: ReliableSender::new(),
+        })
+    }
+
+    /// Sequence the transactions of the client until it stops, then send the last epoch, tell the shards
+    /// the sequencer closed, and wait until they received every message.
+    pub async fn run(&mut self) -> Result<()> {
+        info!("Sequencer {} started", self.id);
+
+        // Handlers of the batches not yet acknowledged by the shards.
+        let mut pending_acks = FuturesUnordered::new();
+        let mut epochs = interval(Duration::from_millis(self.batch_interval));
+
+        loop {
+            tokio::select! {
+                transaction = self.rx_transaction.recv() => match transaction {
+                    Some(transaction) => {
+                        match self.parse_transaction(&transaction) {
+                            Ok((tx_uid, participants)) => {
+                                debug!("Transaction {} sequenced in epoch {}", tx_uid, self.epoch);
+                                let seq = sequence_number(self.epoch, self.id, self.batch_size);
+                                for shard_id in participants {
+                                    self.batches[shard_id as usize].push((seq, transaction.clone()));
+                                }
+                                self.batch_size += 1;
+                            }
+                            Err(e) => warn!("Dropping malformed transaction: {}", e),
+                        }
+                        if self.batch_size == MAX_BATCH_SIZE {
+                            pending_acks.extend(self.close_epoch().await);
+                        }
+                    }
+                    None => break,
+                },
+                _ = epochs.tick() => pending_acks.extend(self.close_epoch().await),
+                Some(_) = pending_acks.next() => (),
+            }
+        }
+
+        pending_acks.extend(self.close_epoch().await);
+        pending_acks.extend(self.close().await);
+        while pending_acks.next().await.is_some() {}
+        Ok(())
+    }
+
+    /// Send the batch of the current epoch to every replica of every shard, and start the next epoch.
+    async fn close_epoch(&mut self) -> Vec<CancelHandler> {
+        let mut handlers = Vec::new();
+        for shard_id in 0..self.batches.len() {
+            let message = SequencerMessage::Batch {
+                epoch: self.epoch,
+                sequencer: self.id,
+                transactions: std::mem::take(&mut self.batches[shard_id]),
+            };
+            let bytes =
+                bincode::serialize(&message).expect("Failed to serialize sequencer message");
+            let addresses = self.nodes[shard_id].clone();
+            handlers.extend(self.network.broadcast(addresses, Bytes::from(bytes)).await);
+        }
+        self.epoch += 1;
+        self.batch_size = 0;
+        handlers
+    }
+
+    /// Tell every replica of every shard that the sequencer sends no batch from the current epoch on.
+    async fn close(&mut self) -> Vec<CancelHandler> {
+        let message = SequencerMessage::Closed {
+            epoch: self.epoch,
+            sequencer: self.id,
+        };
+        let bytes = bincode::serialize(&message).expect("Failed to serialize sequencer message");
+        let addresses = self.nodes.iter().flatten().copied().collect();
+        self.network.broadcast(addresses, Bytes::from(bytes)).await
+    }
+
+    /// Return the uid of a transaction and the (sorted) shards owning the users it touches.
+    fn parse_transaction(&self, transaction: &Transaction) -> Result<(TxUid, Vec<ShardId>)> {
+        let bytes = Bytes::from(transaction.clone());
+        let tx_uid = self.sb_handler.get_transaction_uid(bytes.clone())?;
+        let (_, users) = self.sb_handler.get_transaction_dependency(bytes)?;
+        let participants: BTreeSet<_> = users
+            .into_iter()
+            .map(|user| self.shard_map.shard(user as u64))
+            .collect();
+        Ok((tx_uid, participants.into_iter().collect()))
+    }
+}
@@ -0,0 +1,67 @@
+use super::*;
+use std::env;
+
+fn address(port: u16) -> SocketAddr {
+    format!("127.0.0.1:{}", port).parse().unwrap()
+}
+
+#[test]
+fn round_robin_assignment() {
+    let nodes: Vec<_> = (0..6).map(address).collect();
+    let membership = Membership::round_robin(&nodes, 3).unwrap();
+    assert_eq!(membership.num_shards(), 3);
+    assert_eq!(membership.shards[0].replicas, vec![address(0), address(3)]);
+    assert_eq!(membership.shards[2].replicas, vec![address(2), address(5)]);
+    assert_eq!(membership.shards[1].leader, address(1));
+    assert_eq!(membership.starts(), None);
+}
+
+#[test]
+fn round_robin_needs_one_node_per_shard() {
+    let nodes: Vec<_> = (0..2).map(address).collect();
+    assert!(Membership::round_robin(&nodes, 3).is_err());
+    assert!(Membership::round_robin(&nodes, 0).is_err());
+}
+
+#[test]
+fn leader_comes_first() {
+    let shard = Shard {
+        start: None,
+        leader: address(2),
+        replicas: vec![address(1), address(2), address(3)],
+    };
+    assert_eq!(shard.addresses(), vec![address(2), address(1), address(3)]);
+}
+
+#[test]
+fn import() {
+    let membership = Membership {
+        shards: vec![
+            Shard {
+                start: Some(0),
+                leader: address(1),
+                replicas: vec![address(1), address(2)],
+            },
+            Shard {
+                start: Some(500),
+                leader: address(4),
+                replicas: vec![address(3), address(4)],
+            },
+        ],
+    };
+    let path = env::temp_dir().join("client_test_import.json");
+    let path = path.to_str().unwrap();
+    fs::write(path, serde_json::to_string_pretty(&membership).unwrap()).unwrap();
+    let imported = Membership::import(path).unwrap();
+    assert_eq!(imported, membership);
+    assert_eq!(imported.starts(), Some(vec![0, 500]));
+}
+
+#[test]
+fn import_rejects_unknown_leader() {
+    let json = r#"{"shards": [{"leader": "127.0.0.1:9", "replicas": ["127.0.0.1:1"]}]}"#;
+    let path = env::temp_dir().join("client_test_unknown_leader.json");
+    let path = path.to_str().unwrap();
+    fs::write(path, json).unwrap();
+    assert!(Membership::import(path).is_err());
+}
@@ -0,0 +1,701 @@
+use super::*;
+use crate::config::Shard;
+use crate::decision_log::{DecisionLog, InFlight, LogRecord};
+use crate::messages::QueryReply;
+use crate::replication::{Follower, Replicator};
+use async_trait::async_trait;
+use futures::lock::Mutex as AsyncMutex;
+use futures::sink::SinkExt as _;
+use network::{MessageHandler, Receiver as NetworkReceiver, Writer};
+use smallbank::{ModuloShardMap, SmallBankTx, TransactionMix, TxHeader};
+use std::env;
+use std::error::Error;
+use tokio::sync::mpsc::{channel, unbounded_channel, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::time::sleep;
+
+const TX_SIZE: usize = 64;
+
+/// A participant shard voting with a fixed vote (after `delay`) and delivering every message it
+/// receives.
+#[derive(Clone)]
+struct TestParticipant {
+    vote: Vote,
+    delay: Duration,
+    deliver: Sender<CoordinatorMessage>,
+}
+
+#[async_trait]
+impl MessageHandler for TestParticipant {
+    async fn dispatch(
+        &self,
+        writer: Arc<AsyncMutex<Writer>>,
+        message: Bytes,
+    ) -> Result<(), Box<dyn Error>> {
+        let message: CoordinatorMessage = bincode::deserialize(&message)?;
+        let reply = match message {
+            CoordinatorMessage::Prepare(..) => {
+                sleep(self.delay).await;
+                bincode::serialize(&self.vote)?
+            }
+            CoordinatorMessage::Query { .. } => bincode::serialize(&QueryReply::Balance {
+                checking: 1_000,
+                saving: 1_000,
+            })?,
+            _ => b"Ack".to_vec(),
+        };
+        writer.lock().await.send(Bytes::from(reply)).await?;
+        self.deliver.send(message).await?;
+        Ok(())
+    }
+}
+
+/// Spawn one participant per vote and return their addresses and delivery channels.
+async fn participants(
+    base_port: u16,
+    votes: &[Vote],
+) -> (Vec<SocketAddr>, Vec<Receiver<CoordinatorMessage>>) {
+    slow_participants(base_port, votes, Duration::ZERO).await
+}
+
+/// Spawn one participant per vote, each taking `delay` to vote on a prepare.
+async fn slow_participants(
+    base_port: u16,
+    votes: &[Vote],
+    delay: Duration,
+) -> (Vec<SocketAddr>, Vec<Receiver<CoordinatorMessage>>) {
+    let mut addresses = Vec::new();
+    let mut receivers = Vec::new();
+    for (i, vote) in votes.iter().enumerate() {
+        let address = format!("127.0.0.1:{}", base_port + i as u16)
+            .parse::<SocketAddr>()
+            .unwrap();
+        let (tx, rx) = channel(10);
+        NetworkReceiver::spawn(
+            address,
+            TestParticipant {
+                vote: *vote,
+                delay,
+                deliver: tx,
+            },
+        );
+        addresses.push(address);
+        receivers.push(rx);
+    }
+    sleep(Duration::from_millis(50)).await;
+    (addresses, receivers)
+}
+
+/// Make a SmallBank `send` transaction (type 3) moving `amount` from `from` to `to`.
+fn send_payment(tx_uid: TxUid, from: u32, to: u32, amount: u32) -> Transaction {
+    let header = TxHeader {
+        sample: false,
+        uid: tx_uid,
+    };
+    SmallBankTx::SendPayment { from, to, amount }
+        .encode(header, TX_SIZE)
+        .to_vec()
+}
+
+/// Make a SmallBank `read` transaction (type 6) of the balance of `user`.
+fn read(tx_uid: TxUid, user: u32) -> Transaction {
+    let header = TxHeader {
+        sample: false,
+        uid: tx_uid,
+    };
+    SmallBankTx::Read { user }.encode(header, TX_SIZE).to_vec()
+}
+
+/// Spawn a coordinator for `nodes.len()` shards placed by modulo, `nodes[i]` being the replicas of shard i.
+fn spawn_coordinator(nodes: Vec<Vec<SocketAddr>>) -> Sender<Transaction> {
+    spawn_coordinator_with_feedback(nodes, None)
+}
+
+/// Spawn a coordinator delivering the transactions it commits to `tx_committed`.
+fn spawn_coordinator_with_feedback(
+    nodes: Vec<Vec<SocketAddr>>,
+    tx_committed: Option<UnboundedSender<Transaction>>,
+) -> Sender<Transaction> {
+    let (mut coordinator, tx_transaction) = make_coordinator(nodes, tx_committed);
+    tokio::spawn(async move { coordinator.run().await });
+    tx_transaction
+}
+
+/// Spawn a coordinator serving reads in the specified mode, and return the channel receiving the
+/// replies to the balance queries.
+fn spawn_coordinator_with_reads(
+    nodes: Vec<Vec<SocketAddr>>,
+    read_mode: ReadMode,
+) -> (Sender<Transaction>, UnboundedReceiver<(TxUid, QueryReply)>) {
+    let (mut coordinator, tx_transaction) = make_coordinator(nodes, None);
+    let (tx_balances, rx_balances) = unbounded_channel();
+    coordinator.set_read_mode(read_mode, Some(tx_balances));
+    tokio::spawn(async move { coordinator.run().await });
+    (tx_transaction, rx_balances)
+}
+
+fn make_coordinator(
+    nodes: Vec<Vec<SocketAddr>>,
+    tx_committed: Option<UnboundedSender<Transaction>>,
+) -> (Coordinator, Sender<Transaction>) {
+    let (tx_transaction, rx_transaction) = channel(10);
+    let coordinator = coordinator_with_input(rx_transaction, nodes, tx_committed);
+    (coordinator, tx_transaction)
+}
+
+/// Make a coordinator receiving its transactions from `rx_transaction`.
+fn coordinator_with_input(
+    rx_transaction: Receiver<Transaction>,
+    nodes: Vec<Vec<SocketAddr>>,
+    tx_committed: Option<UnboundedSender<Transaction>>,
+) -> Coordinator {
+    let sb_handler = SmallBankTransactionHandler::new(
+        TX_SIZE,
+        10,
+        0.5,
+        TransactionMix::from_prob_choose_mtx(0.5),
+    );
+    let shard_map = Arc::new(ModuloShardMap::new(nodes.len() as u32));
+    let shards = nodes
+        .into_iter()
+        .map(|replicas| Shard {
+            start: None,
+            leader: replicas[0],
+            replicas,
+        })
+        .collect();
+    let membership = Membership { shards };
+    Coordinator::new(
+        rx_transaction,
+        &membership,
+        shard_map,
+        1_000,
+        sb_handler,
+        tx_committed,
+    )
+}
+
+#[test]
+fn client_uids_do_not_collide() {
+    // Clients number their transactions alike, but the uids hold the id of the client.
+    assert_ne!(client_tx_uid(0, 7), client_tx_uid(1, 7));
+    assert_eq!(client_tx_uid(0, 7), 7);
+    assert_eq!(client_tx_uid(2, 7) >> CLIENT_UID_BITS, 2);
+    // Only the low bits of the index are kept.
+    assert_eq!(client_tx_uid(1, u64::MAX) >> CLIENT_UID_BITS, 1);
+}
+
+#[tokio::test]
+async fn commit_cross_shard_transaction() {
+    let (nodes, mut receivers) = participants(6_000, &[Vote::Yes, Vote::Yes]).await;
+    let tx_transaction = spawn_coordinator(vec![vec![nodes[0]], vec![nodes[1]]]);
+
+    // Users 2 and 3 live on shards 0 and 1.
+    let transaction = send_payment(7, 2, 3, 10);
+    tx_transaction.send(transaction.clone()).await.unwrap();
+
+    // Both shards are asked to prepare, then to commit.
+    for rx in receivers.iter_mut() {
+        let expected = CoordinatorMessage::Prepare(7, transaction.clone());
+        assert_eq!(rx.recv().await, Some(expected));
+        assert_eq!(rx.recv().await, Some(CoordinatorMessage::Commit(7)));
+    }
+}
+
+#[tokio::test]
+async fn abort_if_any_shard_votes_no() {
+    let (nodes, mut receivers) = participants(6_100, &[Vote::Yes, Vote::No]).await;
+    let tx_transaction = spawn_coordinator(vec![vec![nodes[0]], vec![nodes[1]]]);
+
+    let transaction = send_payment(8, 4, 5, 10);
+    tx_transaction.send(transaction.clone()).await.unwrap();
+
+    // Both shards are asked to prepare, then to abort.
+    for rx in receivers.iter_mut() {
+        let expected = CoordinatorMessage::Prepare(8, transaction.clone());
+        assert_eq!(rx.recv().await, Some(expected));
+        assert_eq!(rx.recv().await, Some(CoordinatorMessage::Abort(8)));
+    }
+}
+
+#[tokio::test]
+async fn single_shard_transaction_only_involves_its_shard() {
+    let (nodes, mut receivers) = participants(6_200, &[Vote::Yes, Vote::Yes]).await;
+    let tx_transaction = spawn_coordinator(vec![vec![nodes[0]], vec![nodes[1]]]);
+
+    // Users 2 and 4 both live on shard 0.
+    let transaction = send_payment(9, 2, 4, 10);
+    tx_transaction.send(transaction.clone()).await.unwrap();
+
+    let expected = CoordinatorMessage::Prepare(9, transaction);
+    assert_eq!(receivers[0].recv().await, Some(expected));
+    assert_eq!(
+        receivers[0].recv().await,
+        Some(CoordinatorMessage::Commit(9))
+    );
+
+    // Shard 1 never hears about the transaction.
+    sleep(Duration::from_millis(100)).await;
+    assert!(receivers[1].try_recv().is_err());
+}
+
+#[tokio::test]
+async fn conflicting_transaction_waits_for_locks() {
+    let (nodes, mut receivers) = participants(6_300, &[Vote::Yes, Vote::Yes]).await;
+    let tx_transaction = spawn_coordinator(vec![vec![nodes[0]], vec![nodes[1]]]);
+
+    // Both transactions write user 2, so the second one waits until the first one is decided.
+    let first = send_payment(10, 2, 3, 10);
+    let second = send_payment(11, 2, 5, 10);
+    tx_transaction.send(first.clone()).await.unwrap();
+    tx_transaction.send(second.clone()).await.unwrap();
+
+    let rx = &mut receivers[0];
+    assert_eq!(
+        rx.recv().await,
+        Some(CoordinatorMessage::Prepare(10, first))
+    );
+    assert_eq!(rx.recv().await, Some(CoordinatorMessage::Commit(10)));
+    assert_eq!(
+        rx.recv().await,
+        Some(CoordinatorMessage::Prepare(11, second))
+    );
+    assert_eq!(rx.recv().await, Some(CoordinatorMessage::Commit(11)));
+}
+
+#[tokio::test]
+async fn every_replica_of_a_shard_takes_part() {
+    let votes = [Vote::Yes, Vote::Yes, Vote::Yes, Vote::Yes];
+    let (nodes, mut receivers) = participants(6_400, &votes).await;
+    let tx_transaction =
+        spawn_coordinator(vec![vec![nodes[0], nodes[1]], vec![nodes[2], nodes[3]]]);
+
+    let transaction = send_payment(12, 2, 3, 10);
+    tx_transaction.send(transaction.clone()).await.unwrap();
+
+    for rx in receivers.iter_mut() {
+        let expected = CoordinatorMessage::Prepare(12, transaction.clone());
+        assert_eq!(rx.recv().await, Some(expected));
+        assert_eq!(rx.recv().await, Some(CoordinatorMessage::Commit(12)));
+    }
+}
+
+#[tokio::test]
+async fn abort_if_any_replica_votes_no() {
+    let votes = [Vote::Yes, Vote::Yes, Vote::Yes, Vote::No];
+    let (nodes, mut receivers) = participants(6_500, &votes).await;
+    let tx_transaction =
+        spawn_coordinator(vec![vec![nodes[0], nodes[1]], vec![nodes[2], nodes[3]]]);
+
+    let transaction = send_payment(13, 2, 3, 10);
+    tx_transaction.send(transaction.clone()).await.unwrap();
+
+    for rx in receivers.iter_mut() {
+        let expected = CoordinatorMessage::Prepare(13, transaction.clone());
+        assert_eq!(rx.recv().await, Some(expected));
+        assert_eq!(rx.recv().await, Some(CoordinatorMessage::Abort(13)));
+    }
+}
+
+#[tokio::test]
+async fn drop_malformed_transaction() {
+    let (nodes, mut receivers) = participants(6_600, &[Vote::Yes]).await;
+    let tx_transaction = spawn_coordinator(vec![vec![nodes[0]]]);
+
+    // A truncated transaction and one touching an unknown user are dropped.
+    let mut truncated = send_payment(12, 2, 3, 10);
+    truncated.truncate(12);
+    tx_transaction.send(truncated).await.unwrap();
+    tx_transaction
+        .send(send_payment(13, 2, 100, 10))
+        .await
+        .unwrap();
+
+    // The coordinator keeps processing valid transactions.
+    let transaction = send_payment(14, 2, 3, 10);
+    tx_transaction.send(transaction.clone()).await.unwrap();
+    let rx = &mut receivers[0];
+    assert_eq!(
+        rx.recv().await,
+        Some(CoordinatorMessage::Prepare(14, transaction))
+    );
+    assert_eq!(rx.recv().await, Some(CoordinatorMessage::Commit(14)));
+}
+
+#[tokio::test]
+async fn committed_transactions_are_fed_back() {
+    let (nodes, _receivers) = participants(6_700, &[Vote::Yes, Vote::No]).await;
+    let (tx_committed, mut rx_committed) = unbounded_channel();
+    let tx_transaction =
+        spawn_coordinator_with_feedback(vec![vec![nodes[0]], vec![nodes[1]]], Some(tx_committed));
+
+    // Shard 1 rejects the first transaction. The second one only involves shard 0, and waits for the
+    // first one to be decided since both write user 2.
+    let aborted = send_payment(15, 2, 3, 10);
+    let committed = send_payment(16, 2, 4, 10);
+    tx_transaction.send(aborted).await.unwrap();
+    tx_transaction.send(committed.clone()).await.unwrap();
+
+    // Only the committed transaction is fed back.
+    assert_eq!(rx_committed.recv().await, Some(committed));
+    assert!(rx_committed.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn replica_reads_bypass_two_phase_commit() {
+    let votes = [Vote::Yes, Vote::Yes, Vote::Yes, Vote::Yes];
+    let (nodes, mut receivers) = participants(6_800, &votes).await;
+    let (tx_transaction, mut rx_balances) = spawn_coordinator_with_reads(
+        vec![vec![nodes[0], nodes[1]], vec![nodes[2], nodes[3]]],
+        ReadMode::Replica,
+    );
+
+    // User 3 lives on shard 1: consecutive reads query its two replicas in turn.
+    tx_transaction.send(read(17, 3)).await.unwrap();
+    tx_transaction.send(read(18, 3)).await.unwrap();
+    let balance = QueryReply::Balance {
+        checking: 1_000,
+        saving: 1_000,
+    };
+    let mut replies = vec![
+        rx_balances.recv().await.unwrap(),
+        rx_balances.recv().await.unwrap(),
+    ];
+    replies.sort_by_key(|(tx_uid, _)| *tx_uid);
+    assert_eq!(replies, vec![(17, balance), (18, balance)]);
+    for (replica, tx_uid) in [(2, 17), (3, 18)] {
+        let expected = CoordinatorMessage::Query {
+            tx_uid,
+            user: 3,
+            linearizable: false,
+        };
+        assert_eq!(receivers[replica].recv().await, Some(expected));
+    }
+
+    // Nothing goes through the two-phase commit.
+    sleep(Duration::from_millis(100)).await;
+    assert!(receivers.iter_mut().all(|rx| rx.try_recv().is_err()));
+}
+
+#[tokio::test]
+async fn linearizable_reads_query_the_leader() {
+    let (nodes, mut receivers) = participants(6_900, &[Vote::Yes, Vote::Yes]).await;
+    let (tx_transaction, mut rx_balances) =
+        spawn_coordinator_with_reads(vec![vec![nodes[0], nodes[1]]], ReadMode::Linearizable);
+
+    for tx_uid in [19, 20] {
+        tx_transaction.send(read(tx_uid, 2)).await.unwrap();
+        let expected = CoordinatorMessage::Query {
+            tx_uid,
+            user: 2,
+            linearizable: true,
+        };
+        assert_eq!(receivers[0].recv().await, Some(expected));
+        assert!(rx_balances.recv().await.is_some());
+    }
+    assert!(receivers[1].try_recv().is_err());
+}
+
+#[tokio::test]
+async fn recover_in_flight_transactions() {
+    let (nodes, mut receivers) = participants(7_000, &[Vote::Yes, Vote::Yes]).await;
+    let path = env::temp_dir().join("client_test_recovery.log");
+    let _ = std::fs::remove_file(&path);
+
+    // A previous run prepared two transactions and decided to commit the second one.
+    let (mut log, _) = DecisionLog::open(&path).unwrap();
+    let undecided = send_payment(21, 2, 3, 10);
+    let decided = send_payment(22, 4, 6, 10);
+    log.append(&LogRecord::Prepare(21, undecided)).unwrap();
+    log.append(&LogRecord::Prepare(22, decided)).unwrap();
+    log.append(&LogRecord::Decision(22, Decision::Commit))
+        .unwrap();
+    drop(log);
+
+    let (decision_log, in_flight) = DecisionLog::open(&path).unwrap();
+    let (mut coordinator, _tx_transaction) =
+        make_coordinator(vec![vec![nodes[0]], vec![nodes[1]]], None);
+    coordinator.set_decision_log(decision_log, in_flight);
+    tokio::spawn(async move { coordinator.run().await });
+
+    // The undecided transaction aborts on both shards, and the decided one commits on shard 0.
+    assert_eq!(
+        receivers[0].recv().await,
+        Some(CoordinatorMessage::Abort(21))
+    );
+    assert_eq!(
+        receivers[0].recv().await,
+        Some(CoordinatorMessage::Commit(22))
+    );
+    assert_eq!(
+        receivers[1].recv().await,
+        Some(CoordinatorMessage::Abort(21))
+    );
+
+    // Once acknowledged, the transactions are no longer in flight.
+    sleep(Duration::from_millis(100)).await;
+    assert!(DecisionLog::open(&path).unwrap().1.is_empty());
+}
+
+#[test]
+fn recovered_transactions_are_merged() {
+    let node = "127.0.0.1:1".parse().unwrap();
+    let (mut coordinator, _tx_transaction) = make_coordinator(vec![vec![node]], None);
+    let in_flight = |tx_uid, decision| InFlight {
+        tx_uid,
+        transaction: send_payment(tx_uid, 2, 3, 10),
+        decision,
+    };
+
+    // The decision log and the coordinator group both know transaction 25, only one of them decided.
+    coordinator.recover(vec![in_flight(24, None), in_flight(25, None)]);
+    coordinator.recover(vec![in_flight(25, Some(Decision::Commit))]);
+    assert_eq!(
+        coordinator.recovered,
+        vec![in_flight(24, None), in_flight(25, Some(Decision::Commit))]
+    );
+}
+
+#[tokio::test]
+async fn decisions_are_logged() {
+    let (nodes, mut receivers) = participants(7_100, &[Vote::Yes]).await;
+    let path = env::temp_dir().join("client_test_logged.log");
+    let _ = std::fs::remove_file(&path);
+    let (decision_log, in_flight) = DecisionLog::open(&path).unwrap();
+    let (mut coordinator, tx_transaction) = make_coordinator(vec![vec![nodes[0]]], None);
+    coordinator.set_decision_log(decision_log, in_flight);
+    tokio::spawn(async move { coordinator.run().await });
+
+    let transaction = send_payment(23, 2, 3, 10);
+    tx_transaction.send(transaction.clone()).await.unwrap();
+    let rx = &mut receivers[0];
+    assert_eq!(
+        rx.recv().await,
+        Some(CoordinatorMessage::Prepare(23, transaction.clone()))
+    );
+    assert_eq!(rx.recv().await, Some(CoordinatorMessage::Commit(23)));
+
+    // The prepare, the decision and its acknowledgement are logged in order.
+    sleep(Duration::from_millis(100)).await;
+    let records = DecisionLog::parse(&std::fs::read(&path).unwrap());
+    let expected = vec![
+        LogRecord::Prepare(23, transaction),
+        LogRecord::Decision(23, Decision::Commit),
+        LogRecord::Done(23),
+    ];
+    assert_eq!(records, expected);
+}
+
+#[tokio::test]
+async fn follower_takes_over_from_crashed_coordinator() {
+    let (nodes, mut receivers) = participants(7_300, &[Vote::Yes, Vote::Yes]).await;
+    let group: Vec<SocketAddr> = (7_310..7_313)
+        .map(|port| format!("127.0.0.1:{}", port).parse().unwrap())
+        .collect();
+    for rank in 1..group.len() {
+        let follower = Follower::new(rank, group.clone(), 200);
+        let shards = vec![vec![nodes[0]], vec![nodes[1]]];
+        tokio::spawn(follower.run(move |rx| coordinator_with_input(rx, shards, None)));
+    }
+    sleep(Duration::from_millis(50)).await;
+
+    // The leader replicates two prepares and one commit decision, then crashes.
+    let mut replicator = Replicator::new(0, group[1..].to_vec(), group.len());
+    let undecided = send_payment(31, 2, 3, 10);
+    let decided = send_payment(32, 4, 6, 10);
+    replicator
+        .replicate(LogRecord::Prepare(31, undecided))
+        .await
+        .await
+        .unwrap();
+    replicator
+        .replicate(LogRecord::Prepare(32, decided))
+        .await
+        .await
+        .unwrap();
+    replicator
+        .replicate(LogRecord::Decision(32, Decision::Commit))
+        .await
+        .await
+        .unwrap();
+
+    // A follower takes over: the undecided transaction aborts and the decided one commits.
+    assert_eq!(
+        receivers[0].recv().await,
+        Some(CoordinatorMessage::Abort(31))
+    );
+    assert_eq!(
+        receivers[0].recv().await,
+        Some(CoordinatorMessage::Commit(32))
+    );
+    assert_eq!(
+        receivers[1].recv().await,
+        Some(CoordinatorMessage::Abort(31))
+    );
+
+    // Only one follower took over.
+    sleep(Duration::from_millis(600)).await;
+    assert!(receivers.iter_mut().all(|rx| rx.try_recv().is_err()));
+}
+
+#[tokio::test]
+async fn replicated_coordinator_stops_once_its_input_closes() {
+    let (nodes, mut receivers) = participants(7_600, &[Vote::Yes, Vote::Yes]).await;
+    let group: Vec<SocketAddr> = (7_610..7_612)
+        .map(|port| format!("127.0.0.1:{}", port).parse().unwrap())
+        .collect();
+    let follower = Follower::new(1, group.clone(), 60_000);
+    tokio::spawn(follower.run(|_| unreachable!("The follower should not take over")));
+    sleep(Duration::from_millis(50)).await;
+
+    let (mut coordinator, tx_transaction) =
+        make_coordinator(vec![vec![nodes[0]], vec![nodes[1]]], None);
+    coordinator.set_replication(Replicator::new(0, group[1..].to_vec(), group.len()));
+    let handle = tokio::spawn(async move { coordinator.run().await });
+
+    // The transaction in flight is finished before the coordinator stops.
+    tx_transaction
+        .send(send_payment(40, 2, 3, 10))
+        .await
+        .unwrap();
+    drop(tx_transaction);
+    let stopped = timeout(Duration::from_millis(2_000), handle).await;
+    assert!(stopped
+        .expect("The coordinator did not stop")
+        .unwrap()
+        .is_ok());
+    assert_eq!(
+        receivers[1].recv().await,
+        Some(CoordinatorMessage::Prepare(40, send_payment(40, 2, 3, 10)))
+    );
+    assert_eq!(
+        receivers[1].recv().await,
+        Some(CoordinatorMessage::Commit(40))
+    );
+}
+
+#[tokio::test]
+async fn outcomes_are_reported() {
+    let (nodes, _receivers) = participants(7_500, &[Vote::Yes, Vote::No]).await;
+    let (mut coordinator, tx_transaction) =
+        make_coordinator(vec![vec![nodes[0]], vec![nodes[1]]], None);
+    let (tx_outcomes, mut rx_outcomes) = unbounded_channel();
+    coordinator.set_outcome_reporting(tx_outcomes);
+    tokio::spawn(async move { coordinator.run().await });
+
+    // Shard 1 rejects the cross-shard transaction, user 100 does not exist, and the last transaction
+    // reuses the uid of a transaction in flight.
+    for transaction in [
+        send_payment(20, 2, 3, 10),
+        send_payment(21, 2, 4, 10),
+        send_payment(22, 2, 100, 10),
+        send_payment(21, 6, 8, 10),
+    ] {
+        tx_transaction.send(transaction).await.unwrap();
+    }
+
+    let mut outcomes = Vec::new();
+    for _ in 0..4 {
+        outcomes.push(rx_outcomes.recv().await.unwrap());
+    }
+    outcomes.sort_by_key(|(tx_uid, _)| *tx_uid);
+    assert_eq!(
+        outcomes,
+        vec![
+            (20, TxOutcome::Aborted(AbortReason::VotedNo)),
+            (21, TxOutcome::Aborted(AbortReason::Duplicate)),
+            (21, TxOutcome::Committed),
+            (22, TxOutcome::Aborted(AbortReason::Invalid)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn retry_is_not_aborted_by_the_lock_timer_of_its_first_attempt() {
+    // Prepares are voted on one at a time, each taking 400 ms; locks are held for up to 1 s.
+    let (nodes, _receivers) =
+        slow_participants(7_700, &[Vote::No], Duration::from_millis(400)).await;
+    let (mut coordinator, tx_transaction) = make_coordinator(vec![vec![nodes[0]]], None);
+    let (tx_outcomes, mut rx_outcomes) = unbounded_channel();
+    coordinator.set_outcome_reporting(tx_outcomes);
+    tokio::spawn(async move { coordinator.run().await });
+
+    // The first attempt waits for the locks of transaction 60 (its lock timer fires at 1 s), and
+    // is aborted at 800 ms.
+    let retried = send_payment(70, 2, 4, 10);
+    tx_transaction
+        .send(send_payment(60, 2, 3, 10))
+        .await
+        .unwrap();
+    tx_transaction.send(retried.clone()).await.unwrap();
+    assert_eq!(
+        rx_outcomes.recv().await,
+        Some((60, TxOutcome::Aborted(AbortReason::VotedNo)))
+    );
+    assert_eq!(
+        rx_outcomes.recv().await,
+        Some((70, TxOutcome::Aborted(AbortReason::VotedNo)))
+    );
+
+    // The retry waits for the locks of transaction 80 (until 1.2 s), past the timer of the first
+    // attempt, and gets prepared.
+    tx_transaction
+        .send(send_payment(80, 2, 5, 10))
+        .await
+        .unwrap();
+    tx_transaction.send(retried).await.unwrap();
+    assert_eq!(
+        rx_outcomes.recv().await,
+        Some((80, TxOutcome::Aborted(AbortReason::VotedNo)))
+    );
+    assert_eq!(
+        rx_outcomes.recv().await,
+        Some((70, TxOutcome::Aborted(AbortReason::VotedNo)))
+    );
+}
+
+#[tokio::test]
+async fn retry_is_logged_after_the_first_attempt_is_done() {
+    let (nodes, _receivers) =
+        slow_participants(7_800, &[Vote::No], Duration::from_millis(200)).await;
+    let path = env::temp_dir().join("client_test_retry_logged.log");
+    let _ = std::fs::remove_file(&path);
+    let (decision_log, in_flight) = DecisionLog::open(&path).unwrap();
+    let (mut coordinator, tx_transaction) = make_coordinator(vec![vec![nodes[0]]], None);
+    coordinator.set_decision_log(decision_log, in_flight);
+    let (tx_outcomes, mut rx_outcomes) = unbounded_channel();
+    coordinator.set_outcome_reporting(tx_outcomes);
+    tokio::spawn(async move { coordinator.run().await });
+
+    // The abort of transaction 90 is acknowledged after the prepare of transaction 91 (at 400 ms),
+    // but the client sends it again as soon as it learns the outcome (at 200 ms).
+    let retried = send_payment(90, 2, 3, 10);
+    tx_transaction.send(retried.clone()).await.unwrap();
+    sleep(Duration::from_millis(50)).await;
+    tx_transaction
+        .send(send_payment(91, 4, 5, 10))
+        .await
+        .unwrap();
+    assert_eq!(
+        rx_outcomes.recv().await,
+        Some((90, TxOutcome::Aborted(AbortReason::VotedNo)))
+    );
+    tx_transaction.send(retried.clone()).await.unwrap();
+    while rx_outcomes.recv().await.unwrap().0 != 90 {}
+
+    // The records of the two attempts do not interleave.
+    sleep(Duration::from_millis(100)).await;
+    let records: Vec<_> = DecisionLog::parse(&std::fs::read(&path).unwrap())
+        .into_iter()
+        .filter(|record| match record {
+            LogRecord::Prepare(tx_uid, _)
+            | LogRecord::Decision(tx_uid, _)
+            | LogRecord::Done(tx_uid) => *tx_uid == 90,
+        })
+        .collect();
+    let attempt = vec![
+        LogRecord::Prepare(90, retried),
+        LogRecord::Decision(90, Decision::Abort),
+        LogRecord::Done(90),
+    ];
+    assert_eq!(records, [attempt.clone(), attempt].concat());
+}
@@ -0,0 +1,60 @@
+use super::*;
+use std::env;
+
+fn log_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(name);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn replay_in_flight_transactions() {
+    let path = log_path("client_test_replay.log");
+    let (mut log, in_flight) = DecisionLog::open(&path).unwrap();
+    assert!(in_flight.is_empty());
+    log.append(&LogRecord::Prepare(1, vec![1])).unwrap();
+    log.append(&LogRecord::Prepare(2, vec![2])).unwrap();
+    log.append(&LogRecord::Prepare(3, vec![3])).unwrap();
+    log.append(&LogRecord::Decision(1, Decision::Commit))
+        .unwrap();
+    log.append(&LogRecord::Decision(2, Decision::Abort))
+        .unwrap();
+    log.append(&LogRecord::Done(2)).unwrap();
+    drop(log);
+
+    let expected = vec![
+        InFlight {
+            tx_uid: 1,
+            transaction: vec![1],
+            decision: Some(Decision::Commit),
+        },
+        InFlight {
+            tx_uid: 3,
+            transaction: vec![3],
+            decision: None,
+        },
+    ];
+    let (log, in_flight) = DecisionLog::open(&path).unwrap();
+    assert_eq!(in_flight, expected);
+
+    // The compacted log holds the same in-flight transactions.
+    drop(log);
+    assert_eq!(DecisionLog::open(&path).unwrap().1, expected);
+}
+
+#[test]
+fn torn_record_is_ignored() {
+    let path = log_path("client_test_torn.log");
+    let (mut log, _) = DecisionLog::open(&path).unwrap();
+    log.append(&LogRecord::Prepare(1, vec![1; 10])).unwrap();
+    log.append(&LogRecord::Prepare(2, vec![2; 10])).unwrap();
+    drop(log);
+
+    // Simulate a crash in the middle of the second record.
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+
+    let (_, in_flight) = DecisionLog::open(&path).unwrap();
+    assert_eq!(in_flight.len(), 1);
+    assert_eq!(in_flight[0].tx_uid, 1);
+}
@@ -0,0 +1,109 @@
+use super::*;
+
+#[test]
+fn shared_locks_are_compatible() {
+    let mut locks = LockManager::new();
+    assert!(locks.acquire(1, &[(10, LockMode::Shared)]));
+    assert!(locks.acquire(2, &[(10, LockMode::Shared)]));
+    assert!(!locks.acquire(3, &[(10, LockMode::Exclusive)]));
+}
+
+#[test]
+fn exclusive_lock_blocks_everyone_else() {
+    let mut locks = LockManager::new();
+    assert!(locks.acquire(1, &[(10, LockMode::Exclusive)]));
+    assert!(!locks.acquire(2, &[(10, LockMode::Shared)]));
+    assert!(!locks.acquire(3, &[(10, LockMode::Exclusive)]));
+}
+
+#[test]
+fn waiters_are_served_in_fifo_order() {
+    let mut locks = LockManager::new();
+    assert!(locks.acquire(1, &[(10, LockMode::Exclusive)]));
+    assert!(!locks.acquire(2, &[(10, LockMode::Exclusive)]));
+    assert!(!locks.acquire(3, &[(10, LockMode::Exclusive)]));
+
+    assert_eq!(locks.release(1), vec![2]);
+    assert_eq!(locks.release(2), vec![3]);
+    assert!(locks.release(3).is_empty());
+}
+
+#[test]
+fn compatible_waiters_are_woken_together() {
+    let mut locks = LockManager::new();
+    assert!(locks.acquire(1, &[(10, LockMode::Exclusive)]));
+    assert!(!locks.acquire(2, &[(10, LockMode::Shared)]));
+    assert!(!locks.acquire(3, &[(10, LockMode::Shared)]));
+    assert!(!locks.acquire(4, &[(10, LockMode::Exclusive)]));
+
+    let mut ready = locks.release(1);
+    ready.sort_unstable();
+    assert_eq!(ready, vec![2, 3]);
+    assert!(locks.release(2).is_empty());
+    assert_eq!(locks.release(3), vec![4]);
+}
+
+#[test]
+fn shared_requests_queue_behind_waiting_writers() {
+    let mut locks = LockManager::new();
+    assert!(locks.acquire(1, &[(10, LockMode::Shared)]));
+    assert!(!locks.acquire(2, &[(10, LockMode::Exclusive)]));
+
+    // Transaction 3 is compatible with the holder but must not overtake the waiting writer.
+    assert!(!locks.acquire(3, &[(10, LockMode::Shared)]));
+    assert_eq!(locks.release(1), vec![2]);
+    assert_eq!(locks.release(2), vec![3]);
+}
+
+#[test]
+fn waiting_transaction_holds_only_smaller_keys() {
+    let mut locks = LockManager::new();
+    assert!(locks.acquire(1, &[(20, LockMode::Exclusive)]));
+
+    // Transaction 2 locks key 10 and waits for key 20.
+    assert!(!locks.acquire(2, &[(20, LockMode::Exclusive), (10, LockMode::Exclusive)]));
+    assert!(!locks.acquire(3, &[(10, LockMode::Exclusive)]));
+
+    assert_eq!(locks.release(1), vec![2]);
+    assert_eq!(locks.release(2), vec![3]);
+}
+
+#[test]
+fn opposite_lock_orders_do_not_deadlock() {
+    let mut locks = LockManager::new();
+
+    // Both transactions lock keys 10 and 20, listed in opposite orders.
+    assert!(locks.acquire(1, &[(10, LockMode::Exclusive), (20, LockMode::Exclusive)]));
+    assert!(!locks.acquire(2, &[(20, LockMode::Exclusive), (10, LockMode::Exclusive)]));
+    assert_eq!(locks.release(1), vec![2]);
+}
+
+#[test]
+fn cancelled_waiter_unblocks_the_queue() {
+    let mut locks = LockManager::new();
+    assert!(locks.acquire(1, &[(10, LockMode::Shared)]));
+    assert!(!locks.acquire(2, &[(10, LockMode::Exclusive)]));
+    assert!(!locks.acquire(3, &[(10, LockMode::Shared)]));
+
+    // Transaction 2 times out: transaction 3 can now share the key with transaction 1.
+    assert!(locks.is_waiting(2));
+    assert_eq!(locks.release(2), vec![3]);
+    assert!(!locks.is_waiting(2));
+    assert!(!locks.is_waiting(3));
+}
+
+#[test]
+fn release_frees_every_key() {
+    let mut locks = LockManager::new();
+    assert!(locks.acquire(1, &[(10, LockMode::Exclusive), (20, LockMode::Shared)]));
+    locks.release(1);
+    assert!(locks.acquire(2, &[(10, LockMode::Exclusive), (20, LockMode::Exclusive)]));
+}
+
+#[test]
+fn duplicate_keys_take_the_strongest_mode() {
+    let mut locks = LockManager::new();
+    assert!(locks.acquire(1, &[(10, LockMode::Shared), (10, LockMode::Exclusive)]));
+    assert!(!locks.acquire(2, &[(10, LockMode::Shared)]));
+    assert_eq!(locks.release(1), vec![2]);
+}
@@ -0,0 +1,140 @@
+use super::*;
+use crate::retry::RetryPolicy;
+use tokio::time::sleep;
+
+#[tokio::test]
+async fn completions_resolve_with_their_outcome() {
+    let mut tracker =
+        OutcomeTracker::with_retries(Duration::from_secs(10), RetryPolicy::default(), 0);
+    let completion = tracker.completion(1, vec![1]);
+    tracker.register(2, vec![2]);
+    assert_eq!(tracker.pending(), 2);
+
+    tracker.complete(1, TxOutcome::Committed);
+    let completion = completion.await.unwrap();
+    assert_eq!(
+        (completion.tx_uid, completion.outcome),
+        (1, TxOutcome::Committed)
+    );
+
+    // Outcomes of unknown or already completed transactions are ignored.
+    assert!(tracker.complete(1, TxOutcome::TimedOut).is_none());
+    assert!(tracker.complete(3, TxOutcome::Committed).is_none());
+    assert_eq!(tracker.pending(), 1);
+}
+
+#[tokio::test]
+async fn transactions_time_out() {
+    let mut tracker =
+        OutcomeTracker::with_retries(Duration::from_millis(50), RetryPolicy::default(), 0);
+    let completion = tracker.completion(1, vec![1]);
+    assert!(tracker.expire().is_empty());
+    sleep(Duration::from_millis(60)).await;
+    tracker.register(2, vec![2]);
+
+    let expired = tracker.expire();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(completion.await.unwrap().outcome, TxOutcome::TimedOut);
+    assert_eq!(tracker.pending(), 1);
+}
+
+#[test]
+fn stats_summarize_the_outcomes() {
+    let mut tracker =
+        OutcomeTracker::with_retries(Duration::from_secs(10), RetryPolicy::default(), 0);
+    for tx_uid in 0..4 {
+        tracker.register(tx_uid, vec![tx_uid as u8]);
+    }
+    tracker.complete(0, TxOutcome::Committed);
+    tracker.complete(1, TxOutcome::Committed);
+    tracker.complete(2, TxOutcome::Aborted(AbortReason::VotedNo));
+    tracker.complete(3, TxOutcome::Aborted(AbortReason::LockTimeout));
+
+    let stats = tracker.stats();
+    assert_eq!(stats.total(), 4);
+    assert_eq!(stats.committed, 2);
+    assert_eq!(stats.aborted[&AbortReason::VotedNo], 1);
+    assert!(stats.latency_percentile(50.0) <= stats.latency_percentile(100.0));
+    let summary = stats.to_string();
+    assert!(summary.starts_with(
+        "4 transactions: 2 committed, 2 aborted, 0 timed out (voted no: 1, lock timeout: 1); \
+         2 committed at the first attempt, 0 retries; commit latency: mean"
+    ));
+}
+
+#[tokio::test]
+async fn transient_aborts_are_retried() {
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        base_backoff: Duration::from_millis(20),
+        max_backoff: Duration::from_millis(20),
+        retry_on: vec![AbortReason::LockTimeout],
+    };
+    let mut tracker = OutcomeTracker::with_retries(Duration::from_secs(10), policy, 0);
+    let completion = tracker.completion(1, vec![1]);
+    tracker.register(2, vec![2]);
+    tracker.register(3, vec![3]);
+
+    // Only the transient abort is retried, after its backoff.
+    assert!(tracker
+        .complete(1, TxOutcome::Aborted(AbortReason::LockTimeout))
+        .is_none());
+    let aborted = tracker.complete(2, TxOutcome::Aborted(AbortReason::VotedNo));
+    assert_eq!(aborted.unwrap().attempts, 1);
+    tracker.complete(3, TxOutcome::Committed);
+    assert!(tracker.due_retries().is_empty());
+    sleep(Duration::from_millis(25)).await;
+    assert_eq!(tracker.due_retries(), vec![vec![1]]);
+
+    // The retry commits.
+    tracker.complete(1, TxOutcome::Committed);
+    let completion = completion.await.unwrap();
+    assert_eq!(
+        (completion.outcome, completion.attempts),
+        (TxOutcome::Committed, 2)
+    );
+    let stats = tracker.stats();
+    assert_eq!((stats.committed, stats.committed_first_attempt), (2, 1));
+    assert_eq!(stats.retries, 1);
+}
+
+#[tokio::test]
+async fn retries_stop_after_the_last_attempt() {
+    let policy = RetryPolicy {
+        max_attempts: 2,
+        base_backoff: Duration::ZERO,
+        ..RetryPolicy::default()
+    };
+    let mut tracker = OutcomeTracker::with_retries(Duration::from_secs(10), policy, 0);
+    tracker.register(1, vec![1]);
+    let outcome = TxOutcome::Aborted(AbortReason::NoVote);
+    assert!(tracker.complete(1, outcome).is_none());
+    assert_eq!(tracker.due_retries().len(), 1);
+    let completion = tracker.complete(1, outcome).unwrap();
+    assert_eq!((completion.outcome, completion.attempts), (outcome, 2));
+    assert_eq!(tracker.pending(), 0);
+}
+
+#[tokio::test]
+async fn duplicates_do_not_complete_the_transaction_in_flight() {
+    let mut tracker =
+        OutcomeTracker::with_retries(Duration::from_secs(10), RetryPolicy::default(), 0);
+    let completion = tracker.completion(1, vec![1]);
+
+    // A second transaction with the same uid leaves the first one tracked, and the coordinator
+    // dropping it does not complete the first one.
+    tracker.register(1, vec![2]);
+    assert!(tracker
+        .complete(1, TxOutcome::Aborted(AbortReason::Duplicate))
+        .is_none());
+    assert_eq!(tracker.pending(), 1);
+
+    tracker.complete(1, TxOutcome::Committed);
+    assert_eq!(completion.await.unwrap().outcome, TxOutcome::Committed);
+    let stats = tracker.stats();
+    assert_eq!(
+        (stats.total(), stats.committed, stats.duplicates),
+        (1, 1, 1)
+    );
+    assert!(stats.to_string().contains("1 duplicates dropped"));
+}
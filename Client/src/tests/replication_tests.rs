@@ -0,0 +1,79 @@
+use super::*;
+use crate::coordinator::Decision;
+use tokio::time::timeout;
+
+fn address(port: u16) -> SocketAddr {
+    format!("127.0.0.1:{}", port).parse().unwrap()
+}
+
+/// Spawn a follower that never takes over.
+async fn spawn_follower(rank: usize, group: Vec<SocketAddr>) {
+    let follower = Follower::new(rank, group, 60_000);
+    tokio::spawn(follower.run(|_| unreachable!("The follower should not take over")));
+    sleep(Duration::from_millis(50)).await;
+}
+
+#[tokio::test]
+async fn stale_leader_is_fenced() {
+    let group = vec![address(7_200), address(7_201)];
+    spawn_follower(1, group.clone()).await;
+
+    // The leader of epoch 0 replicates a prepare.
+    let mut replicator = Replicator::new(0, vec![group[1]], group.len());
+    let record = LogRecord::Prepare(1, vec![1]);
+    replicator.replicate(record).await.await.unwrap();
+    replicator
+        .replicate(LogRecord::Decision(1, Decision::Commit))
+        .await
+        .await
+        .unwrap();
+
+    // A newer epoch learns the decision...
+    let message = ReplicationMessage::Takeover { epoch: 3 };
+    let bytes = Bytes::from(bincode::serialize(&message).unwrap());
+    let reply = ReliableSender::new().send(group[1], bytes).await.await;
+    let expected = ReplicationReply::State(vec![InFlight {
+        tx_uid: 1,
+        transaction: vec![1],
+        decision: Some(Decision::Commit),
+    }]);
+    assert_eq!(
+        bincode::deserialize::<ReplicationReply>(&reply.unwrap()).unwrap(),
+        expected
+    );
+
+    // ...and the old leader can no longer replicate anything.
+    assert!(replicator
+        .replicate(LogRecord::Done(1))
+        .await
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn replicate_without_followers() {
+    // A group of one coordinator needs no acknowledgement.
+    let mut replicator = Replicator::new(0, Vec::new(), 1);
+    assert!(replicator
+        .replicate(LogRecord::Prepare(1, vec![1]))
+        .await
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn failed_takeover_does_not_fence_the_leader() {
+    // The third coordinator of the group is down: the follower cannot gather a majority.
+    let group = vec![address(7_900), address(7_901), address(7_902)];
+    let follower = Follower::new(1, group.clone(), 100);
+    tokio::spawn(follower.run(|_| unreachable!("The follower should not take over")));
+
+    // The leader was silent long enough for the follower to try (and fail) to take over.
+    sleep(Duration::from_millis(400)).await;
+    let mut replicator = Replicator::new(0, group[1..].to_vec(), group.len());
+    let replicated = replicator.replicate(LogRecord::Prepare(1, vec![1])).await;
+    assert!(timeout(Duration::from_millis(1_000), replicated)
+        .await
+        .unwrap()
+        .is_ok());
+}
@@ -0,0 +1,44 @@
+use super::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng as _;
+
+#[test]
+fn backoff_doubles_up_to_the_maximum() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        base_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_millis(1_000),
+        ..RetryPolicy::default()
+    };
+    let mut rng = StdRng::seed_from_u64(0);
+    for (attempts, backoff) in [
+        (1, 100),
+        (2, 200),
+        (3, 400),
+        (4, 800),
+        (5, 1_000),
+        (40, 1_000),
+    ] {
+        // A random half of the backoff is skipped.
+        let delay = policy.backoff(attempts, &mut rng);
+        assert!(delay >= Duration::from_millis(backoff / 2), "{:?}", delay);
+        assert!(delay <= Duration::from_millis(backoff), "{:?}", delay);
+    }
+}
+
+#[test]
+fn only_transient_reasons_are_retried() {
+    let policy = RetryPolicy {
+        max_attempts: 2,
+        retry_on: RetryPolicy::parse_reasons("lock_timeout, no_vote").unwrap(),
+        ..RetryPolicy::default()
+    };
+    assert!(policy.should_retry(AbortReason::LockTimeout, 1));
+    assert!(!policy.should_retry(AbortReason::LockTimeout, 2));
+    assert!(!policy.should_retry(AbortReason::VotedNo, 1));
+    assert!(!RetryPolicy::default().should_retry(AbortReason::NoVote, 1));
+
+    assert!(RetryPolicy::parse_reasons("invalid").is_err());
+    assert!(RetryPolicy::parse_reasons("duplicate").is_err());
+    assert!(RetryPolicy::parse_reasons("deadlock").is_err());
+}
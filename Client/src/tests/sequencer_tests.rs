@@ -0,0 +1,157 @@
+use super::*;
+use crate::config::Shard;
+use async_trait::async_trait;
+use futures::lock::Mutex as AsyncMutex;
+use futures::sink::SinkExt as _;
+use network::{MessageHandler, Receiver as NetworkReceiver, Writer};
+use smallbank::{ModuloShardMap, SmallBankTx, TransactionMix, TxHeader};
+use std::error::Error;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::time::sleep;
+
+const TX_SIZE: usize = 64;
+
+/// A shard delivering every batch it receives.
+#[derive(Clone)]
+struct TestShard {
+    deliver: Sender<SequencerMessage>,
+}
+
+#[async_trait]
+impl MessageHandler for TestShard {
+    async fn dispatch(
+        &self,
+        writer: Arc<AsyncMutex<Writer>>,
+        message: Bytes,
+    ) -> Result<(), Box<dyn Error>> {
+        let message: SequencerMessage = bincode::deserialize(&message)?;
+        writer.lock().await.send(Bytes::from("Ack")).await?;
+        self.deliver.send(message).await?;
+        Ok(())
+    }
+}
+
+fn send_payment(tx_uid: TxUid, from: u32, to: u32) -> Transaction {
+    let header = TxHeader {
+        sample: false,
+        uid: tx_uid,
+    };
+    SmallBankTx::SendPayment {
+        from,
+        to,
+        amount: 10,
+    }
+    .encode(header, TX_SIZE)
+    .to_vec()
+}
+
+#[test]
+fn sequence_numbers_follow_the_global_order() {
+    assert!(sequence_number(0, 0, 1) < sequence_number(0, 1, 0));
+    assert!(sequence_number(0, MAX_SEQUENCERS - 1, MAX_BATCH_SIZE - 1) < sequence_number(1, 0, 0));
+    assert!(sequence_number(3, 2, 7) < sequence_number(3, 2, 8));
+}
+
+#[test]
+fn sequencer_ids_are_bounded() {
+    let (_, rx_transaction) = channel(1);
+    let sb_handler = SmallBankTransactionHandler::new(
+        TX_SIZE,
+        10,
+        0.5,
+        TransactionMix::from_prob_choose_mtx(0.5),
+    );
+    let sequencer = Sequencer::new(
+        rx_transaction,
+        MAX_SEQUENCERS,
+        &Membership { shards: Vec::new() },
+        Arc::new(ModuloShardMap::new(1)),
+        10,
+        sb_handler,
+    );
+    assert!(sequencer.is_err());
+}
+
+#[tokio::test]
+async fn batches_hold_the_transactions_of_their_shard() {
+    let mut addresses = Vec::new();
+    let mut receivers = Vec::new();
+    for port in [7_400, 7_401] {
+        let address: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+        let (tx, rx) = channel(10);
+        NetworkReceiver::spawn(address, TestShard { deliver: tx });
+        addresses.push(address);
+        receivers.push(rx);
+    }
+    sleep(Duration::from_millis(50)).await;
+
+    let shards = addresses
+        .iter()
+        .map(|address| Shard {
+            start: None,
+            leader: *address,
+            replicas: vec![*address],
+        })
+        .collect();
+    let sb_handler = SmallBankTransactionHandler::new(
+        TX_SIZE,
+        10,
+        0.5,
+        TransactionMix::from_prob_choose_mtx(0.5),
+    );
+    let (tx_transaction, rx_transaction) = channel(10);
+    let mut sequencer = Sequencer::new(
+        rx_transaction,
+        1,
+        &Membership { shards },
+        Arc::new(ModuloShardMap::new(2)),
+        60_000,
+        sb_handler,
+    )
+    .unwrap();
+    let handle = tokio::spawn(async move { sequencer.run().await });
+
+    // Users 2 and 4 live on shard 0, user 3 on shard 1.
+    let cross_shard = send_payment(1, 2, 3);
+    let single_shard = send_payment(2, 2, 4);
+    tx_transaction.send(cross_shard.clone()).await.unwrap();
+    tx_transaction.send(single_shard.clone()).await.unwrap();
+    drop(tx_transaction);
+    handle.await.unwrap().unwrap();
+    sleep(Duration::from_millis(50)).await;
+
+    // Every shard receives a batch per epoch, with the transactions touching it in order, then learns
+    // that the sequencer closed.
+    let mut sequenced = Vec::new();
+    for receiver in receivers.iter_mut() {
+        let mut transactions = Vec::new();
+        let mut epoch = 0;
+        loop {
+            match receiver.try_recv().unwrap() {
+                SequencerMessage::Batch {
+                    epoch: e,
+                    sequencer,
+                    transactions: batch,
+                } => {
+                    assert_eq!((e, sequencer), (epoch, 1));
+                    transactions.extend(batch);
+                    epoch += 1;
+                }
+                SequencerMessage::Closed {
+                    epoch: e,
+                    sequencer,
+                } => {
+                    assert_eq!((e, sequencer), (epoch, 1));
+                    break;
+                }
+            }
+        }
+        assert!(receiver.try_recv().is_err());
+        sequenced.push(transactions);
+    }
+    let (seq, transaction) = &sequenced[0][0];
+    assert_eq!(transaction, &cross_shard);
+    assert_eq!(sequenced[0][1].1, single_shard);
+    assert!(sequenced[0][1].0 > *seq);
+    assert_eq!(sequenced[1], vec![(*seq, cross_shard)]);
+}
@@ -47,16 +47,27 @@ class CommandMaker:
                 f'--store {store} --parameters {parameters} --size {size} --n_users {n_users} --shard {shard_assignment} --skew_factor {skew_factor} --prob_choose_mtx {prob_choose_mtx} worker --id {id}')
 
     @staticmethod
-    def run_client(address, size, n_users, shard_assignment, skew_factor, prob_choose_mtx, rate, nodes):
+    def run_client(address, size, n_users, membership, skew_factor, prob_choose_mtx, rate, seed=None, client_id=0, mix=None, cross_shard=None, reads=None, execution=None, max_attempts=None):
         assert isinstance(address, str)
         assert isinstance(size, int) and size > 0
         assert isinstance(rate, int) and rate >= 0
-        assert isinstance(nodes, list)
-        assert all(isinstance(x, str) for x in nodes)
-        assert isinstance(shard_assignment, list)
-        shards = f'--shards {" ".join(shard_assignment)}' if shard_assignment else ''
-        nodes = f'--nodes {" ".join(nodes)}' if nodes else ''
-        return f'./benchmark_client {address} --size {size} --n_users {n_users} {shards} --skew_factor {skew_factor} --prob_choose_mtx {prob_choose_mtx} --rate {rate} {nodes}'
+        assert isinstance(membership, str)
+        assert seed is None or isinstance(seed, int)
+        assert mix is None or isinstance(mix, str)
+        assert isinstance(client_id, int) and client_id >= 0
+        seed = '' if seed is None else f' --seed {seed}'
+        assert cross_shard is None or 0 <= cross_shard <= 1
+        mix = '' if mix is None else f' --mix {mix}'
+        cross_shard = '' if cross_shard is None else f' --cross_shard {cross_shard}'
+        assert reads in (None, 'ordered', 'replica', 'linearizable')
+        reads = '' if reads is None else f' --reads {reads}'
+        assert execution in (None, 'two_phase_commit', 'deterministic')
+        execution = '' if execution is None else f' --execution {execution}'
+        assert max_attempts is None or (isinstance(max_attempts, int) and max_attempts > 0)
+        max_attempts = '' if max_attempts is None else f' --max_attempts {max_attempts}'
+        return (f'./benchmark_client {address} --size {size} --n_users {n_users} '
+                f'--membership {membership} --shard_map range --skew_factor {skew_factor} '
+                f'--prob_choose_mtx {prob_choose_mtx} --rate {rate} --client_id {client_id}{seed}{mix}{cross_shard}{reads}{execution}{max_attempts}')
 
     @staticmethod
     def kill():
@@ -161,6 +161,43 @@ class LocalCommittee(Committee):
         super().__init__(addresses, port)
 
 
+class Membership:
+    ''' The membership file (shared by the client and the nodes) looks as follows:
+        {
+            "shards": [
+                {
+                    "start": 0,
+                    "leader": x.x.x.x:x,
+                    "replicas": [x.x.x.x:x, ...]
+                },
+                ...
+            ]
+        }
+    '''
+
+    def __init__(self, workers_addresses, shards):
+        ''' The `workers_addresses` field is the output of `Committee.workers_addresses` (the i-th
+            worker of each node serves shard i) and `shards` is the list of (first, last) users of
+            each shard. The leader of each shard is the worker of the first node.
+        '''
+        assert isinstance(workers_addresses, list)
+        assert all(len(x) == len(shards) for x in workers_addresses)
+
+        self.json = {'shards': []}
+        for i, (start, _) in enumerate(shards):
+            replicas = [addresses[i][1] for addresses in workers_addresses]
+            self.json['shards'].append({
+                'start': start,
+                'leader': replicas[0],
+                'replicas': replicas
+            })
+
+    def print(self, filename):
+        assert isinstance(filename, str)
+        with open(filename, 'w') as f:
+            dump(self.json, f, indent=4, sort_keys=True)
+
+
 class NodeParameters:
     def __init__(self, json):
         inputs = []
@@ -237,6 +274,23 @@ class BenchParameters:
             self.skew_factor = float(json['skew_factor'])
 
             self.prob_choose_mtx = float(json['prob_choose_mtx'])
+
+            self.seed = int(json['seed']) if 'seed' in json else None
+
+            # Transaction mix of the clients (replaces prob_choose_mtx), e.g. 'oltpbench' or 'send=40,split=5'.
+            self.mix = str(json['mix']) if 'mix' in json else None
+
+            # Probability that send and split transactions span several shards (uncontrolled if absent).
+            self.cross_shard = float(json['cross_shard']) if 'cross_shard' in json else None
+
+            # How the clients serve reads: 'ordered' (default), 'replica' or 'linearizable'.
+            self.reads = str(json['reads']) if 'reads' in json else None
+
+            # How cross-shard transactions execute: 'two_phase_commit' (default) or 'deterministic'.
+            self.execution = str(json['execution']) if 'execution' in json else None
+
+            # How many times the clients send aborted transactions (no retry if absent).
+            self.max_attempts = int(json['max_attempts']) if 'max_attempts' in json else None
            
             self.duration = int(json['duration'])
 
@@ -5,7 +5,7 @@ from os.path import basename, splitext
 from time import sleep
 
 from benchmark.commands import CommandMaker
-from benchmark.config import Key, LocalCommittee, NodeParameters, BenchParameters, ConfigError
+from benchmark.config import Key, LocalCommittee, Membership, NodeParameters, BenchParameters, ConfigError
 from benchmark.logs import LogParser, ParseError
 from benchmark.fairness_logs import FairnessParseError, FairnessLogParser
 from benchmark.utils import Print, BenchError, PathMaker
@@ -93,6 +93,10 @@ class LocalBench:
             Print.info(f'shard_assignment_list = {shard_assignment_list}')
             Print.info(f'worker_to_shard_assignment = {worker_to_shard_assignment}')
 
+            # Describe the shards and their replicas to the clients.
+            membership = Membership(workers_addresses, self.bench_parameters.shards)
+            membership.print(PathMaker.membership_file())
+
             # Run the clients (they will wait for the nodes to be ready).
             # TODO: find correct rate_share
             rate_share = ceil(rate / committee.workers())
@@ -105,11 +109,17 @@ class LocalBench:
                     address,
                     self.tx_size,
                     self.n_users,
-                    shard_assignment_list,
+                    PathMaker.membership_file(),
                     self.skew_factor,
                     self.prob_choose_mtx,
                     rate_share,
-                    [x for y in workers_addresses for _, x in y]
+                    self.seed,
+                    i,
+                    self.mix,
+                    self.cross_shard,
+                    self.reads,
+                    self.execution,
+                    self.max_attempts
                 )
                 log_file = PathMaker.client_log_file(i)
                 self._background_run(cmd, log_file)
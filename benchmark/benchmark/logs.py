@@ -34,9 +34,10 @@ class LogParser:
                 results = p.map(self._parse_clients, clients)
         except (ValueError, IndexError, AttributeError) as e:
             raise ParseError(f'Failed to parse clients\' logs: {e}')
-        self.size, self.rate, self.start, misses, self.sent_samples \
+        self.size, self.rate, self.start, misses, self.sent_samples, outcomes \
             = zip(*results)
         self.misses = sum(misses)
+        self.outcomes = [x for x in outcomes if x is not None]
 
         # Parse the primaries logs.
         try:
@@ -94,7 +95,15 @@ class LogParser:
         tmp = findall(r'\[(.*Z) .* sample transaction (\d+)', log)
         samples = {int(s): self._to_posix(t) for t, s in tmp}
 
-        return size, rate, start, misses, samples
+        # The summary of the outcomes of the transactions (committed, aborted, timed out, committed at
+        # the first attempt and retries), if tracked.
+        tmp = search(
+            r'Transaction outcomes: \d+ transactions: (\d+) committed, (\d+) aborted, (\d+) timed out'
+            r'.*; (\d+) committed at the first attempt, (\d+) retries', log
+        )
+        outcomes = tuple(int(x) for x in tmp.groups()) if tmp is not None else None
+
+        return size, rate, start, misses, samples, outcomes
 
     def _parse_primaries(self, log):
         if search(r'(?:panicked|Error)', log) is not None:
@@ -205,6 +214,20 @@ class LogParser:
         end_to_end_tps, end_to_end_bps, duration = self._end_to_end_throughput()
         end_to_end_latency = self._end_to_end_latency() * 1_000
 
+        outcomes = ''
+        if self.outcomes:
+            committed, aborted, timed_out, first_attempt, retries = (
+                sum(x) for x in zip(*self.outcomes)
+            )
+            outcomes = (
+                '\n'
+                f' Committed transactions: {committed:,}\n'
+                f' Committed at the first attempt: {first_attempt:,}\n'
+                f' Aborted transactions: {aborted:,}\n'
+                f' Timed out transactions: {timed_out:,}\n'
+                f' Retries: {retries:,}\n'
+            )
+
         return (
             '\n'
             '-----------------------------------------\n'
@@ -235,6 +258,7 @@ class LogParser:
             f' End-to-end TPS: {round(end_to_end_tps):,} tx/s\n'
             f' End-to-end BPS: {round(end_to_end_bps):,} B/s\n'
             f' End-to-end latency: {round(end_to_end_latency):,} ms\n'
+            f'{outcomes}'
             '-----------------------------------------\n'
         )
 
@@ -10,7 +10,7 @@ from math import ceil
 from copy import deepcopy
 import subprocess
 
-from benchmark.config import Committee, Key, NodeParameters, BenchParameters, ConfigError
+from benchmark.config import Committee, Key, Membership, NodeParameters, BenchParameters, ConfigError
 from benchmark.utils import BenchError, Print, PathMaker, progress_bar
 from benchmark.commands import CommandMaker
 from benchmark.logs import LogParser, ParseError
@@ -223,21 +223,34 @@ class Bench:
         Print.info('Booting clients...')
         Print.info(f'n_users = {bench_parameters.n_users}, skew_factor = {bench_parameters.skew_factor}, prob_choose_mtx = {bench_parameters.prob_choose_mtx}')
         workers_addresses = committee.workers_addresses(faults)
+        membership = Membership(workers_addresses, bench_parameters.shards)
+        membership.print(PathMaker.membership_file())
         rate_share = ceil(rate / committee.workers())
+        client_id = 0
         for i, addresses in enumerate(workers_addresses):
             for (id, address) in addresses:
                 host = Committee.ip(address)
+                c = Connection(host, user='ubuntu', connect_kwargs=self.connect)
+                c.put(PathMaker.membership_file(), '.')
                 cmd = CommandMaker.run_client(
                     address,
                     bench_parameters.tx_size,
                     bench_parameters.n_users,
+                    PathMaker.membership_file(),
                     bench_parameters.skew_factor,
                     bench_parameters.prob_choose_mtx,
                     rate_share,
-                    [x for y in workers_addresses for _, x in y]
+                    bench_parameters.seed,
+                    client_id,
+                    bench_parameters.mix,
+                    bench_parameters.cross_shard,
+                    bench_parameters.reads,
+                    bench_parameters.execution,
+                    bench_parameters.max_attempts
                 )
                 log_file = PathMaker.client_log_file(i, id)
                 self._background_run(host, cmd, log_file)
+                client_id += 1
 
         # Run the primaries (except the faulty ones).
         Print.info('Booting primaries...')
@@ -586,6 +599,10 @@ class CloudLabBench:
         Print.info(f'shard_assignment_list = {shard_assignment_list}')
         Print.info(f'worker_to_shard_assignment = {worker_to_shard_assignment}')
 
+        # Describe the shards and their replicas to the clients.
+        membership = Membership(workers_addresses, bench_parameters.shards)
+        membership.print(PathMaker.membership_file())
+
         # Run the clients (they will wait for the nodes to be ready).
         # Filter all faulty nodes from the client addresses (or they will wait
         # for the faulty nodes to be online).
@@ -598,15 +615,23 @@ class CloudLabBench:
             # Print.info(f'parties = {max(bench_parameters.nodes)} party idx = {int(i/max(bench_parameters.nodes))} worker idx = {i%bench_parameters.workers}')
             (id, address) = workers_addresses[int(i/max(bench_parameters.nodes))][i%bench_parameters.workers]
             host = Committee.ip(address)
+            c = Connection(host, user=self.cloudlab_username)
+            c.put(PathMaker.membership_file(), '.')
             cmd = CommandMaker.run_client(
                 address,
                 bench_parameters.tx_size,
                 bench_parameters.n_users,
-                shard_assignment_list,
+                PathMaker.membership_file(),
                 bench_parameters.skew_factor,
                 bench_parameters.prob_choose_mtx,
                 rate_share,
-                [x for y in workers_addresses for _, x in y]
+                bench_parameters.seed,
+                i,
+                bench_parameters.mix,
+                bench_parameters.cross_shard,
+                bench_parameters.reads,
+                bench_parameters.execution,
+                bench_parameters.max_attempts
             )
             log_file = PathMaker.client_log_file(i)
             self._background_run(host, cmd, log_file)
@@ -27,6 +27,10 @@ class PathMaker:
     def parameters_file():
         return '.parameters.json'
 
+    @staticmethod
+    def membership_file():
+        return '.membership.json'
+
     @staticmethod
     def key_file(i):
         assert isinstance(i, int) and i >= 0
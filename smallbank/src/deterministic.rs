@@ -0,0 +1,223 @@
+use crate::shard_map::{ShardId, ShardMap};
+use crate::state::{execute_on, Account, AccountError, AccountState, ExecutionOutcome};
+use crate::transaction::SmallBankTx;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Arc;
+
+#[cfg(test)]
+#[path = "tests/deterministic_tests.rs"]
+pub mod deterministic_tests;
+
+/// The position of a transaction in the global order of a sequencer.
+pub type Sequence = u64;
+
+/// The balances of its own accounts that a shard sends to the other participants of a transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteReads {
+    pub seq: Sequence,
+    /// The shard that read the balances.
+    pub shard_id: ShardId,
+    pub reads: Vec<(Account, u32, u32)>,
+}
+
+/// What a shard must do after executing the transactions it could.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Reads to send to other shards, with their recipients.
+    pub outbox: Vec<(Vec<ShardId>, RemoteReads)>,
+    /// The outcome of every transaction executed, in order.
+    pub executed: Vec<(Sequence, ExecutionOutcome)>,
+}
+
+/// Executes the transactions of a global order on one shard, without two-phase commit (as in Calvin).
+///
+/// Every participant of a transaction executes it in the global order: when the transaction reaches
+/// the head of its queue, a shard reads the accounts of the transaction it holds and sends them to the
+/// other participants. Once it received their reads, every participant executes the transaction against
+/// the same balances, so they all reach the same outcome, and each of them applies the updates of its
+/// own accounts. Single-shard transactions execute as soon as they reach the head of the queue.
+pub struct DeterministicShard {
+    shard_id: ShardId,
+    shard_map: Arc<dyn ShardMap>,
+    /// The sequenced transactions not executed yet, in order.
+    queue: VecDeque<(Sequence, SmallBankTx)>,
+    /// The last sequence number received.
+    last: Option<Sequence>,
+    /// The reads received from other shards for queued (or future) transactions, by sender.
+    remote: HashMap<Sequence, HashMap<ShardId, RemoteReads>>,
+    /// The reads of the head of the queue, once sent to the other participants.
+    local: Option<RemoteReads>,
+}
+
+impl DeterministicShard {
+    pub fn new(shard_id: ShardId, shard_map: Arc<dyn ShardMap>) -> Self {
+        Self {
+            shard_id,
+            shard_map,
+            queue: VecDeque::new(),
+            last: None,
+            remote: HashMap::new(),
+            local: None,
+        }
+    }
+
+    /// The shards owning the users of a transaction (sorted).
+    pub fn participants(&self, tx: &SmallBankTx) -> Vec<ShardId> {
+        let (_, users) = tx.dependency();
+        users
+            .into_iter()
+            .map(|user| self.shard_map.shard(user as u64))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Queue the next transaction of the global order. Transactions must be sequenced in increasing
+    /// order; the ones this shard does not take part in are ignored.
+    pub fn sequence(&mut self, seq: Sequence, tx: SmallBankTx) {
+        assert!(
+            self.last.is_none_or(|last| seq > last),
+            "Transaction {} sequenced out of order",
+            seq
+        );
+        self.last = Some(seq);
+        if self.participants(&tx).contains(&self.shard_id) {
+            self.queue.push_back((seq, tx));
+        } else {
+            self.remote.remove(&seq);
+        }
+    }
+
+    /// Store the reads sent by another participant. Reads of transactions this shard already executed
+    /// (or does not take part in) are ignored, and a second copy of the reads of a shard replaces the
+    /// first one.
+    pub fn receive(&mut self, reads: RemoteReads) {
+        let queued = self
+            .queue
+            .binary_search_by_key(&reads.seq, |(seq, _)| *seq)
+            .is_ok();
+        if !queued && self.last.is_some_and(|last| reads.seq <= last) {
+            return;
+        }
+        self.remote
+            .entry(reads.seq)
+            .or_default()
+            .insert(reads.shard_id, reads);
+    }
+
+    /// The number of transactions waiting to execute.
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Execute the queued transactions in order, until one of them waits for the reads of another
+    /// shard.
+    pub fn advance<S: AccountState + ?Sized>(&mut self, state: &mut S) -> Progress {
+        let mut progress = Progress::default();
+        while let Some((seq, tx)) = self.queue.front() {
+            let seq = *seq;
+            let others: Vec<_> = self
+                .participants(tx)
+                .into_iter()
+                .filter(|x| *x != self.shard_id)
+                .collect();
+            if others.is_empty() {
+                progress.executed.push((seq, execute_on(state, tx)));
+                self.queue.pop_front();
+                continue;
+            }
+
+            // The head reads its local accounts once every earlier transaction executed. The state may
+            // hold copies of accounts of other shards, but only their owner knows their balance.
+            if self.local.as_ref().map(|x| x.seq) != Some(seq) {
+                let reads = RemoteReads {
+                    seq,
+                    shard_id: self.shard_id,
+                    reads: read_set(tx)
+                        .into_iter()
+                        .filter(|(_, user)| self.shard_map.shard(*user as u64) == self.shard_id)
+                        .filter_map(|(account, user)| {
+                            state
+                                .balance(account, user)
+                                .ok()
+                                .map(|amount| (account, user, amount))
+                        })
+                        .collect(),
+                };
+                progress.outbox.push((others.clone(), reads.clone()));
+                self.local = Some(reads);
+            }
+
+            // Only the reads of the participants count.
+            let received = self.remote.get(&seq);
+            if !others
+                .iter()
+                .all(|shard| received.is_some_and(|x| x.contains_key(shard)))
+            {
+                break;
+            }
+
+            // Every participant now holds the same balances.
+            let mut snapshot = Snapshot::default();
+            let remote = self.remote.remove(&seq).unwrap_or_default();
+            let local = self.local.take().expect("The head read its local accounts");
+            let participants = others.iter().map(|shard| &remote[shard]);
+            for reads in participants.chain(std::iter::once(&local)) {
+                for (account, user, amount) in &reads.reads {
+                    // Each participant only answers for its own accounts.
+                    if self.shard_map.shard(*user as u64) == reads.shard_id {
+                        snapshot.balances.insert((*account, *user), *amount);
+                    }
+                }
+            }
+            let outcome = execute_on(&mut snapshot, tx);
+            for ((account, user), amount) in snapshot.writes {
+                if self.shard_map.shard(user as u64) == self.shard_id {
+                    state.set_balance(account, user, amount);
+                }
+            }
+            progress.executed.push((seq, outcome));
+            self.queue.pop_front();
+        }
+        progress
+    }
+}
+
+/// The accounts a transaction reads (every account it updates is read first).
+fn read_set(tx: &SmallBankTx) -> Vec<(Account, u32)> {
+    match tx {
+        SmallBankTx::DepositSaving { user, .. } => vec![(Account::Saving, *user)],
+        SmallBankTx::Amalgamate { user } => {
+            vec![(Account::Saving, *user), (Account::Checking, *user)]
+        }
+        _ => {
+            let (_, users) = tx.dependency();
+            users
+                .into_iter()
+                .map(|user| (Account::Checking, user))
+                .collect()
+        }
+    }
+}
+
+/// The balances read by every participant of a transaction, and the updates of its execution.
+#[derive(Default)]
+struct Snapshot {
+    balances: HashMap<(Account, u32), u32>,
+    writes: Vec<((Account, u32), u32)>,
+}
+
+impl AccountState for Snapshot {
+    fn balance(&self, account: Account, user_id: u32) -> Result<u32, AccountError> {
+        // The owner of an account always sends it: only accounts that do not exist are missing.
+        self.balances
+            .get(&(account, user_id))
+            .copied()
+            .ok_or(AccountError::Unknown)
+    }
+
+    fn set_balance(&mut self, account: Account, user_id: u32, amount: u32) {
+        self.balances.insert((account, user_id), amount);
+        self.writes.push(((account, user_id), amount));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::state::{execute_on, Account, AccountError, AccountState, ExecutionOutcome};
+use crate::transaction::SmallBankTx;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::HashMap;
+
+#[cfg(test)]
+#[path = "tests/executor_tests.rs"]
+pub mod executor_tests;
+
+/// Executes ordered blocks of transactions with the same outcomes and final state as executing them
+/// one after the other.
+pub trait BlockExecutor {
+    /// Execute a block of transactions and return the outcome of each of them.
+    fn execute<S: AccountState + Sync + ?Sized>(
+        &self,
+        state: &mut S,
+        block: &[SmallBankTx],
+    ) -> Vec<ExecutionOutcome>;
+}
+
+/// Executes blocks of transactions on a thread pool, using their declared dependencies.
+///
+/// The block is split into waves: a transaction runs in the wave after the last earlier transaction
+/// it conflicts with (two transactions conflict if they touch a common user and one of them writes).
+/// The transactions of a wave run in parallel against the state left by the previous waves, and their
+/// updates are applied once the whole wave has executed.
+pub struct ParallelExecutor {
+    pool: ThreadPool,
+}
+
+impl ParallelExecutor {
+    pub fn new(n_threads: usize) -> Self {
+        Self {
+            pool: thread_pool(n_threads),
+        }
+    }
+
+    /// Group the indices of the transactions of a block into waves of non-conflicting transactions.
+    pub fn schedule(block: &[SmallBankTx]) -> Vec<Vec<usize>> {
+        // The last wave reading and writing every user.
+        let mut last_read: HashMap<u32, usize> = HashMap::new();
+        let mut last_write: HashMap<u32, usize> = HashMap::new();
+        let mut waves: Vec<Vec<usize>> = Vec::new();
+        for (index, tx) in block.iter().enumerate() {
+            let (access, users) = tx.dependency();
+            let wave = users
+                .iter()
+                .map(|user| {
+                    let after_write = last_write.get(user).map_or(0, |x| x + 1);
+                    match access {
+                        'r' => after_write,
+                        _ => after_write.max(last_read.get(user).map_or(0, |x| x + 1)),
+                    }
+                })
+                .max()
+                .unwrap_or(0);
+            let last = if access == 'r' {
+                &mut last_read
+            } else {
+                &mut last_write
+            };
+            for user in users {
+                let entry = last.entry(user).or_insert(wave);
+                *entry = (*entry).max(wave);
+            }
+            if wave == waves.len() {
+                waves.push(Vec::new());
+            }
+            waves[wave].push(index);
+        }
+        waves
+    }
+}
+
+impl BlockExecutor for ParallelExecutor {
+    fn execute<S: AccountState + Sync + ?Sized>(
+        &self,
+        state: &mut S,
+        block: &[SmallBankTx],
+    ) -> Vec<ExecutionOutcome> {
+        let mut outcomes = vec![ExecutionOutcome::Committed; block.len()];
+        for wave in Self::schedule(block) {
+            let base: &S = state;
+            let results: Vec<_> = self.pool.install(|| {
+                wave.par_iter()
+                    .map(|index| {
+                        let mut overlay = Overlay {
+                            base,
+                            writes: Vec::new(),
+                        };
+                        let outcome = execute_on(&mut overlay, &block[*index]);
+                        (*index, outcome, overlay.writes)
+                    })
+                    .collect()
+            });
+            for (index, outcome, writes) in results {
+                outcomes[index] = outcome;
+                for (account, user_id, amount) in writes {
+                    state.set_balance(account, user_id, amount);
+                }
+            }
+        }
+        outcomes
+    }
+}
+
+pub(crate) fn thread_pool(n_threads: usize) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .num_threads(n_threads)
+        .thread_name(|i| format!("executor-{}", i))
+        .build()
+        .expect("Failed to build the executor thread pool")
+}
+
+/// Buffers the updates of a transaction on top of a shared state.
+struct Overlay<'a, S: ?Sized> {
+    base: &'a S,
+    writes: Vec<(Account, u32, u32)>,
+}
+
+impl<S: AccountState + ?Sized> AccountState for Overlay<'_, S> {
+    fn balance(&self, account: Account, user_id: u32) -> Result<u32, AccountError> {
+        match self
+            .writes
+            .iter()
+            .rev()
+            .find(|(a, u, _)| *a == account && *u == user_id)
+        {
+            Some((_, _, amount)) => Ok(*amount),
+            None => self.base.balance(account, user_id),
+        }
+    }
+
+    fn set_balance(&mut self, account: Account, user_id: u32, amount: u32) {
+        self.writes.push((account, user_id, amount));
+    }
+}
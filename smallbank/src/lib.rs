@@ -1,23 +1,66 @@
-use rand::distributions::{Distribution, Bernoulli, Uniform};
+mod deterministic;
+mod executor;
+mod merkle;
+mod mix;
+mod optimistic;
+#[cfg(feature = "persistent")]
+mod persistent;
+mod shard_map;
+mod state;
+mod transaction;
+
+#[cfg(test)]
+#[path = "tests/handler_tests.rs"]
+pub mod handler_tests;
+
+pub use crate::deterministic::{DeterministicShard, Progress, RemoteReads, Sequence};
+pub use crate::executor::{BlockExecutor, ParallelExecutor};
+pub use crate::merkle::{account_leaf, AccountProof, Digest, MerkleTree};
+pub use crate::mix::{MixError, TransactionMix, N_TX_TYPES, TX_TYPE_NAMES};
+pub use crate::optimistic::OptimisticExecutor;
+#[cfg(feature = "persistent")]
+pub use crate::persistent::{PersistenceError, PersistentAccounts};
+pub use crate::shard_map::{
+    ConsistentHashShardMap, LookupShardMap, ModuloShardMap, RangeShardMap, ShardId, ShardMap, UserId,
+};
+pub use crate::state::{
+    apply_updates, execute_on, Account, AccountError, AccountState, ExecutionOutcome,
+    INITIAL_BALANCE,
+};
+pub use crate::transaction::{
+    DecodeError, SmallBankTx, TxHeader, MAX_TX_LEN, SPLIT_PARTY_SIZE_MAX, TX_DATA_BYTE,
+    TX_TYPE_BYTE,
+};
+
+use rand::distributions::{Bernoulli, Distribution, WeightedIndex};
 use rand_distr::Zipf;
-use bytes::BufMut as _;
-use bytes::BytesMut;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::cmp;
-use std::collections::HashSet;
+use std::sync::Arc;
 use bytes::Bytes;
 
 const MAX_DEPOSIT: u32 = 50;
 const MAX_AMOUNT: u32 = u32::MAX;
 const SPLIT_PARTY_SIZE_MIN: u32 = 3;
-const SPLIT_PARTY_SIZE_MAX: u32 = 5;
-const TX_DATA_BYTE: usize = 10;
+/// How many times a counterparty is sampled from the user distribution before falling back to a
+/// uniform choice among the users of the right shards.
+const MAX_COUNTERPARTY_SAMPLES: usize = 100;
 
+/// The accounts of the bank. A partitioned bank only holds the accounts of the users owned by one
+/// shard.
 #[derive(Clone)]
-struct SmallBank{
-    // n_users: u64,
+pub struct SmallBank{
+    n_users: u64,
     checking_accounts: Vec<u32>,
     saving_accounts: Vec<u32>,
+    // The shard owning the accounts, if the bank is partitioned (otherwise the index of a user in the
+    // account vectors is its id). The index of an owned user is its rank in the shard: the sorted owned
+    // users are listed if the shard map cannot compute it (see `ShardMap::index_in_shard`).
+    partition: Option<(Arc<dyn ShardMap>, ShardId, Option<Vec<u32>>)>,
+    // The Merkle tree over the accounts, with one leaf per user at its index in the account vectors,
+    // if the bank maintains a state commitment.
+    commitment: Option<MerkleTree>,
 }
 
 impl SmallBank{
@@ -26,117 +69,332 @@ impl SmallBank{
         let mut saving_accounts: Vec<u32> = Vec::new();
 
         for _ in 0..n_users{
-            checking_accounts.push(1000);
-            saving_accounts.push(1000);
+            checking_accounts.push(INITIAL_BALANCE);
+            saving_accounts.push(INITIAL_BALANCE);
         }
 
         SmallBank {
-            // n_users: n_users,
+            n_users: n_users,
             checking_accounts: checking_accounts,
             saving_accounts: saving_accounts,
+            partition: None,
+            commitment: None,
         }
     }
 
-    // pub fn get_n_users(&self) -> u64{
-    //     return self.n_users;
-    // }
+    /// Make a bank only holding the accounts of the users that `shard_map` places on `shard_id`.
+    pub fn partitioned(n_users: u64, shard_map: Arc<dyn ShardMap>, shard_id: ShardId) -> Self {
+        let is_owned = |user_id: &u64| shard_map.shard(*user_id) == shard_id;
+        let (n_owned, owned) = match shard_map.index_in_shard(0){
+            Some(_) => ((0..n_users).filter(is_owned).count(), None),
+            None => {
+                let owned: Vec<u32> = (0..n_users).filter(is_owned).map(|user_id| user_id as u32).collect();
+                (owned.len(), Some(owned))
+            }
+        };
 
-    pub fn deposit_checking(&mut self, user_id: u32, amount: u32){
-        if MAX_AMOUNT - self.checking_accounts[user_id  as usize] > amount{
-            self.checking_accounts[user_id  as usize] += amount;
+        SmallBank {
+            n_users: n_users,
+            checking_accounts: vec![INITIAL_BALANCE; n_owned],
+            saving_accounts: vec![INITIAL_BALANCE; n_owned],
+            partition: Some((shard_map, shard_id, owned)),
+            commitment: None,
         }
     }
 
-    pub fn deposit_saving(&mut self, user_id: u32, amount: u32){
-        if MAX_AMOUNT - self.saving_accounts[user_id  as usize] > amount{
-            self.saving_accounts[user_id  as usize] += amount;
-        }
+    /// The number of accounts of each type held by the bank.
+    pub fn len(&self) -> usize{
+        return self.checking_accounts.len();
     }
 
-    pub fn withdraw_checking(&mut self, user_id: u32, amount: u32){
-        if self.checking_accounts[user_id  as usize] >= amount{
-            self.checking_accounts[user_id  as usize] -= amount;
-        }
+    pub fn is_empty(&self) -> bool{
+        return self.checking_accounts.is_empty();
     }
 
-    pub fn withdraw_saving(&mut self, user_id: u32, amount: u32){
-        if self.saving_accounts[user_id  as usize] >= amount{
-            self.saving_accounts[user_id  as usize] -= amount;
+    /// The position of the accounts of a user in the account vectors.
+    fn _index(&self, user_id: u32) -> Result<usize, AccountError>{
+        if user_id as u64 >= self.n_users{
+            return Err(AccountError::Unknown);
         }
+        return match &self.partition{
+            None => Ok(user_id as usize),
+            Some((_, _, Some(owned))) => owned.binary_search(&user_id).map_err(|_| AccountError::NotOwned),
+            Some((shard_map, shard_id, None)) => {
+                if shard_map.shard(user_id as u64) != *shard_id{
+                    return Err(AccountError::NotOwned);
+                }
+                Ok(shard_map.index_in_shard(user_id as u64).expect("The shard map indexes every user") as usize)
+            }
+        };
     }
 
+    /// The checking balance of a user. Users not owned by a partitioned bank are assumed to hold their
+    /// initial balance.
     pub fn get_checking_amount(&self, user_id: u32) -> u32{
-        return self.checking_accounts[user_id as usize];
+        return self._get_amount(Account::Checking, user_id);
     }
 
+    /// The saving balance of a user, see `get_checking_amount`.
     pub fn get_saving_amount(&self, user_id:u32) -> u32{
-        return self.saving_accounts[user_id as usize];
+        return self._get_amount(Account::Saving, user_id);
     }
+
+    /// The checking and saving balances of a user.
+    pub fn get_balance(&self, user_id: u32) -> Result<(u32, u32), AccountError>{
+        let index = self._index(user_id)?;
+        return Ok((self.checking_accounts[index], self.saving_accounts[index]));
+    }
+
+    /// Maintain a Merkle tree over the balances, so that replicas can compare their state through
+    /// `state_root` and prove the balances of an account with `account_proof`. Every balance update then
+    /// costs a logarithmic number of hashes.
+    pub fn enable_commitment(&mut self){
+        let mut leaves: Vec<Digest> = vec![[0u8; 32]; self.len()];
+        for user_id in 0..self.n_users as u32{
+            if let Ok(index) = self._index(user_id){
+                leaves[index] = account_leaf(user_id, self.checking_accounts[index], self.saving_accounts[index]);
+            }
+        }
+        self.commitment = Some(MerkleTree::new(leaves));
+    }
+
+    /// The root of the Merkle tree over the balances, if the bank maintains a state commitment.
+    pub fn state_root(&self) -> Option<Digest>{
+        return self.commitment.as_ref().map(MerkleTree::root);
+    }
+
+    /// Prove the balances of a user against `state_root`. Returns `None` if the bank does not maintain a
+    /// state commitment or does not hold the accounts of the user.
+    pub fn account_proof(&self, user_id: u32) -> Option<AccountProof>{
+        let tree = self.commitment.as_ref()?;
+        let index = self._index(user_id).ok()?;
+        return Some(AccountProof{
+            user_id: user_id,
+            checking: self.checking_accounts[index],
+            saving: self.saving_accounts[index],
+            index: index,
+            siblings: tree.proof(index),
+        });
+    }
+
+    fn _get_amount(&self, account: Account, user_id: u32) -> u32{
+        return match self.balance(account, user_id){
+            Ok(amount) => amount,
+            Err(AccountError::NotOwned) => INITIAL_BALANCE,
+            Err(AccountError::Unknown) => panic!("Unknown user {}", user_id),
+        };
+    }
+}
+
+impl AccountState for SmallBank{
+    fn balance(&self, account: Account, user_id: u32) -> Result<u32, AccountError>{
+        let index = self._index(user_id)?;
+        return Ok(match account{
+            Account::Checking => self.checking_accounts[index],
+            Account::Saving => self.saving_accounts[index],
+        });
+    }
+
+    fn set_balance(&mut self, account: Account, user_id: u32, amount: u32){
+        let index = self._index(user_id).expect("Balances are only set on owned accounts");
+        match account{
+            Account::Checking => self.checking_accounts[index] = amount,
+            Account::Saving => self.saving_accounts[index] = amount,
+        }
+        if let Some(tree) = self.commitment.as_mut(){
+            tree.update(index, account_leaf(user_id, self.checking_accounts[index], self.saving_accounts[index]));
+        }
+    }
+}
+
+/// How the handler keeps up to date the balances it draws transaction amounts against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BalanceTracking{
+    /// Amounts are drawn against the initial balances, unless transactions are executed explicitly.
+    None,
+    /// Every generated transaction is applied to the balances as soon as it is generated.
+    Generated,
+    /// The caller feeds committed transactions back through `execute_transaction`.
+    Committed,
 }
 
 #[derive(Clone)]
 pub struct SmallBankTransactionHandler{
     tx_size: usize,
-    // n_users: u64,
+    n_users: u64,
     // skew_factor: f64,
     // prob_choose_mtx: f64,
     user_distribution: Zipf<f64>,
-    tx_type_distribution: Bernoulli,
-    mtx_distribution: Uniform<i32>,
+    tx_type_distribution: WeightedIndex<f64>,
     small_bank: SmallBank,
+    // All the randomness of the workload comes from this generator, so that a seed determines the
+    // whole transaction stream.
+    rng: StdRng,
+    balance_tracking: BalanceTracking,
+    // When set, the counterparties of multi-user transactions are placed in (or out of) the shard of
+    // their first user: the transaction is cross-shard with the specified probability. The users of
+    // each shard are listed once, to draw counterparties among them.
+    cross_shard: Option<(Arc<dyn ShardMap>, Bernoulli, Arc<Vec<Vec<u32>>>)>,
 }
 
 impl SmallBankTransactionHandler{
 
-    pub fn new(tx_size: usize, n_users: u64, skew_factor: f64, prob_choose_mtx: f64) -> Self {
+    /// Make a handler generating transactions according to `mix` (see
+    /// `TransactionMix::from_prob_choose_mtx` for the legacy read/modify mix).
+    pub fn new(tx_size: usize, n_users: u64, skew_factor: f64, mix: TransactionMix) -> Self {
+        return Self::with_seed(tx_size, n_users, skew_factor, mix, rand::random());
+    }
+
+    /// Make a handler generating a reproducible transaction stream: two handlers created with the same
+    /// parameters and seed generate the same transactions.
+    pub fn with_seed(tx_size: usize, n_users: u64, skew_factor: f64, mix: TransactionMix, seed: u64) -> Self {
         let user_distribution = Zipf::new(n_users-1, skew_factor).unwrap(); 
-        let tx_type_distribution = Bernoulli::new(prob_choose_mtx).unwrap();
-        let mtx_distribution = Uniform::from(0..6);
+        let tx_type_distribution = mix.distribution();
         let small_bank = SmallBank::new(n_users);
 
         SmallBankTransactionHandler {
             tx_size: tx_size,
-            // n_users: n_users,
+            n_users: n_users,
             // prob_choose_mtx: prob_choose_mtx,
             // skew_factor: skew_factor,
             user_distribution: user_distribution,
             tx_type_distribution: tx_type_distribution,
-            mtx_distribution: mtx_distribution,
             small_bank: small_bank,
+            rng: StdRng::seed_from_u64(seed),
+            balance_tracking: BalanceTracking::None,
+            cross_shard: None,
+        }
+    }
+
+    /// Make `send` and `split` transactions cross-shard (according to `shard_map`) with probability
+    /// `prob_cross_shard`.
+    pub fn set_cross_shard_probability(&mut self, shard_map: Arc<dyn ShardMap>, prob_cross_shard: f64){
+        let distribution = Bernoulli::new(prob_cross_shard).unwrap();
+        let mut users_by_shard: Vec<Vec<u32>> = vec![Vec::new(); shard_map.num_shards() as usize];
+        for user_id in 0..self.n_users{
+            users_by_shard[shard_map.shard(user_id) as usize].push(user_id as u32);
         }
+        self.cross_shard = Some((shard_map, distribution, Arc::new(users_by_shard)));
     }
 
-    fn _get_bytes_to_u32(&self, tx_bytes:&[u8]) -> u32{
-        return u32::from_be_bytes(tx_bytes.try_into().unwrap());
+    /// Only hold the accounts of the users that `shard_map` places on `shard_id`: executing a
+    /// transaction touching other users fails with `ExecutionOutcome::NotOwned`. The balances are reset
+    /// to their initial value.
+    pub fn set_partition(&mut self, shard_map: Arc<dyn ShardMap>, shard_id: ShardId){
+        let commitment = self.small_bank.commitment.is_some();
+        self.small_bank = SmallBank::partitioned(self.n_users, shard_map, shard_id);
+        if commitment{
+            self.small_bank.enable_commitment();
+        }
     }
 
-    fn _get_bytes_to_u64(&self, tx_bytes:&[u8]) -> u64{
-        return u64::from_be_bytes(tx_bytes.try_into().unwrap());
+    /// The checking and saving balances of a user, without going through a transaction. Nodes serve
+    /// balance queries with it.
+    pub fn get_balance(&self, user_id: u32) -> Result<(u32, u32), AccountError>{
+        return self.small_bank.get_balance(user_id);
     }
 
-    fn _generate_random(&self, min:u32, max: u32) -> u32{
+    /// Maintain a state commitment over the balances, see `SmallBank::enable_commitment`.
+    pub fn enable_state_commitment(&mut self){
+        self.small_bank.enable_commitment();
+    }
+
+    /// The root of the Merkle tree over the balances held by the handler, if it maintains a state
+    /// commitment. Replicas that executed the same transactions have the same root.
+    pub fn state_root(&self) -> Option<Digest>{
+        return self.small_bank.state_root();
+    }
+
+    /// Prove the balances of a user against `state_root`.
+    pub fn account_proof(&self, user_id: u32) -> Option<AccountProof>{
+        return self.small_bank.account_proof(user_id);
+    }
+
+    pub fn set_balance_tracking(&mut self, balance_tracking: BalanceTracking){
+        self.balance_tracking = balance_tracking;
+    }
+
+    pub fn get_balance_tracking(&self) -> BalanceTracking{
+        return self.balance_tracking;
+    }
+
+    fn _generate_random(&mut self, min:u32, max: u32) -> u32{
         let mut max = max;
         if max == MAX_AMOUNT{
             max = MAX_AMOUNT-1;
         }
-        return rand::thread_rng().gen_range(min..max+1);
-        // return rand::thread_rng().gen::<u32>() % max_number;
+        return self.rng.gen_range(min..max+1);
+        // return self.rng.gen::<u32>() % max_number;
     }
 
-    fn _sample_user(&self) -> u64{
-        return self.user_distribution.sample(&mut rand::thread_rng()) as u64;
+    fn _sample_user(&mut self) -> u64{
+        return self.user_distribution.sample(&mut self.rng) as u64;
     }
 
-    fn _sample_tx_type(&self) -> bool{
-        return self.tx_type_distribution.sample(&mut rand::thread_rng());
+    fn _sample_tx_type(&mut self) -> u8{
+        return self.tx_type_distribution.sample(&mut self.rng) as u8;
     }
 
-    fn _sample_mtx(&self) -> i32{
-        return self.mtx_distribution.sample(&mut rand::thread_rng());
+    /// Decide whether the next multi-user transaction must be cross-shard (`None` if the placement of
+    /// users is unconstrained).
+    fn _sample_cross_shard(&mut self) -> Option<bool>{
+        return match &self.cross_shard{
+            Some((_, distribution, _)) => Some(distribution.sample(&mut self.rng)),
+            None => None,
+        };
+    }
+
+    /// Sample a counterparty of `user_id` not in `exclude`: in another shard if `cross_shard` is true,
+    /// in the same shard if it is false, and anywhere if it is `None`.
+    fn _sample_counterparty(&mut self, user_id: u32, cross_shard: Option<bool>, exclude: &[u32]) -> u32{
+        let (shard_map, users_by_shard) = match (&self.cross_shard, cross_shard){
+            (Some((shard_map, _, users_by_shard)), Some(_)) => (shard_map.clone(), users_by_shard.clone()),
+            _ => return self._sample_user() as u32,
+        };
+        let shard = shard_map.shard(user_id as u64);
+        let eligible_shard = |candidate: u32| -> bool{
+            (shard_map.shard(candidate as u64) != shard) == cross_shard.unwrap()
+        };
+
+        // Follow the user distribution as long as it produces eligible users.
+        for _ in 0..MAX_COUNTERPARTY_SAMPLES{
+            let candidate = self._sample_user() as u32;
+            if eligible_shard(candidate) && !exclude.contains(&candidate){
+                return candidate;
+            }
+        }
+
+        // Otherwise pick uniformly among the users of the eligible shards that are not excluded, if any.
+        let candidates: Vec<&[u32]> = users_by_shard.iter().enumerate()
+            .filter(|(x, _)| (*x as ShardId != shard) == cross_shard.unwrap())
+            .map(|(_, users)| users.as_slice())
+            .collect();
+        let n_candidates: usize = candidates.iter().map(|users| users.len()).sum();
+        let mut excluded: Vec<u32> = exclude.iter().copied().filter(|x| eligible_shard(*x)).collect();
+        excluded.sort();
+        excluded.dedup();
+        if n_candidates == excluded.len(){
+            return self._sample_user() as u32;
+        }
+        loop{
+            let mut position = self.rng.gen_range(0..n_candidates);
+            let mut candidate = None;
+            for users in &candidates{
+                if position < users.len(){
+                    candidate = Some(users[position]);
+                    break;
+                }
+                position -= users.len();
+            }
+            let candidate = candidate.expect("Positions fall in the candidate shards");
+            if !excluded.contains(&candidate){
+                return candidate;
+            }
+        }
     }
 
     /// Transaction type: 0
-    fn _generate_tx_deposit_saving(&self, tx: &mut BytesMut){
+    fn _generate_tx_deposit_saving(&mut self) -> SmallBankTx{
         // transaction_savings
         let user_id:u32 = self._sample_user() as u32;
         let amount_to_deposit: u32 = self._generate_random(
@@ -144,30 +402,11 @@ impl SmallBankTransactionHandler{
                                         MAX_AMOUNT-self.small_bank.get_saving_amount(user_id)
                                     );
 
-        // Update tx
-        tx.put_u32(user_id);
-        tx.put_u32(amount_to_deposit);
-    }
-
-    fn _get_deposit_saving_tx_dependency(&self, tx: Bytes) -> Vec<u32>{   
-        let mut dependency: Vec<u32> = Vec::new();
-        let start_byte = TX_DATA_BYTE;
-
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        dependency.push(user_id);  
-
-        return dependency;
-    }
-
-    fn _execute_tx_deposit_saving(&mut self, tx: Bytes){
-        let start_byte = TX_DATA_BYTE;
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        let amount_to_deposit: u32 = self._get_bytes_to_u32(&tx[start_byte+4..start_byte+8]);
-        self.small_bank.deposit_saving(user_id, amount_to_deposit);
+        return SmallBankTx::DepositSaving{user: user_id, amount: amount_to_deposit};
     }
 
     /// Transaction type: 1
-    fn _generate_tx_deposit_checking(&self, tx: &mut BytesMut){
+    fn _generate_tx_deposit_checking(&mut self) -> SmallBankTx{
         // Deposit checking
         let user_id:u32 = self._sample_user() as u32;
         let amount_to_deposit: u32 = self._generate_random(
@@ -175,30 +414,11 @@ impl SmallBankTransactionHandler{
                                         MAX_AMOUNT - self.small_bank.get_checking_amount(user_id)
                                     );
 
-        // Update tx
-        tx.put_u32(user_id);
-        tx.put_u32(amount_to_deposit);
-    }
-
-    fn _get_deposit_checking_tx_dependency(&self, tx: Bytes) -> Vec<u32>{
-        let mut dependency: Vec<u32> = Vec::new();
-        let start_byte = TX_DATA_BYTE;
-
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        dependency.push(user_id);
-        
-        return dependency;
-    }
-
-    fn _execute_tx_deposit_checking(&mut self, tx: Bytes){
-        let start_byte = TX_DATA_BYTE;
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        let amount_to_deposit: u32 = self._get_bytes_to_u32(&tx[start_byte+4..start_byte+8]);
-        self.small_bank.deposit_checking(user_id, amount_to_deposit);
+        return SmallBankTx::DepositChecking{user: user_id, amount: amount_to_deposit};
     }
 
     /// Transaction type: 2
-    fn _generate_tx_write_cheque(&self, tx: &mut BytesMut){
+    fn _generate_tx_write_cheque(&mut self) -> SmallBankTx{
         // write_cheque
         let user_id:u32 = self._sample_user() as u32;
         let amount_to_withdraw: u32 = self._generate_random(
@@ -206,33 +426,15 @@ impl SmallBankTransactionHandler{
                                         self.small_bank.get_checking_amount(user_id)
                                     );
 
-        // Update tx
-        tx.put_u32(user_id);
-        tx.put_u32(amount_to_withdraw);
-    }
-
-    fn _get_write_cheque_tx_dependency(&self, tx: Bytes) -> Vec<u32>{
-        let mut dependency: Vec<u32> = Vec::new();
-        let start_byte = TX_DATA_BYTE;
-
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        dependency.push(user_id); 
-
-        return dependency;
-    }
-
-    fn _execute_tx_write_cheque(&mut self, tx: Bytes){
-        let start_byte = TX_DATA_BYTE;
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        let amount_to_withdraw: u32 = self._get_bytes_to_u32(&tx[start_byte+4..start_byte+8]);
-        self.small_bank.withdraw_checking(user_id, amount_to_withdraw);
+        return SmallBankTx::WriteCheque{user: user_id, amount: amount_to_withdraw};
     }
 
     /// Transaction type: 3
-    fn _generate_tx_send(&self, tx: &mut BytesMut){
+    fn _generate_tx_send(&mut self) -> SmallBankTx{
         // send_payment
         let from_user_id: u32 = self._sample_user() as u32;
-        let to_user_id: u32 = self._sample_user() as u32;
+        let cross_shard = self._sample_cross_shard();
+        let to_user_id: u32 = self._sample_counterparty(from_user_id, cross_shard, &[]);
         let amount_to_transfer: u32 = self._generate_random(
                                     0, 
                                     cmp::min(
@@ -240,37 +442,12 @@ impl SmallBankTransactionHandler{
                                         MAX_AMOUNT - self.small_bank.get_checking_amount(to_user_id)
                                     )
                                 );
-        
-        // Update tx
-        tx.put_u32(from_user_id);
-        tx.put_u32(to_user_id);
-        tx.put_u32(amount_to_transfer);
-    }
-
-    fn _get_send_tx_dependency(&self, tx: Bytes) -> Vec<u32>{ 
-        let mut dependency: Vec<u32> = Vec::new();
-        let start_byte = TX_DATA_BYTE;
 
-        let from_user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        dependency.push(from_user_id);
-
-        let to_user_id: u32 = self._get_bytes_to_u32(&tx[start_byte+4..start_byte+8]);
-        dependency.push(to_user_id);    
-
-        return dependency;
-    }
-
-    fn _execute_tx_send(&mut self, tx: Bytes){
-        let start_byte = TX_DATA_BYTE;
-        let from_user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        let to_user_id: u32 = self._get_bytes_to_u32(&tx[start_byte+4..start_byte+8]);
-        let amount_to_transfer: u32 = self._get_bytes_to_u32(&tx[start_byte+8..start_byte+12]);
-        self.small_bank.withdraw_checking(from_user_id, amount_to_transfer);
-        self.small_bank.deposit_checking(to_user_id, amount_to_transfer);
+        return SmallBankTx::SendPayment{from: from_user_id, to: to_user_id, amount: amount_to_transfer};
     }
 
     /// Transaction type: 4
-    fn _generate_tx_split(&self, tx: &mut BytesMut){
+    fn _generate_tx_split(&mut self) -> SmallBankTx{
         // Split transaction
         let party_size: u32 = self._generate_random(
                                 SPLIT_PARTY_SIZE_MIN,
@@ -281,228 +458,140 @@ impl SmallBankTransactionHandler{
                                     party_size/2
                                 );
         let n_payees: u32 = party_size - n_payors;
-        let mut user_set: HashSet<u32> = HashSet::new();
-        while user_set.len() < party_size as usize{
-            user_set.insert(self._sample_user() as u32);
-        }
-        let mut amount_to_split: u32 = 0;
-        let mut count: u32 = 0;
-        // update transaction
-        tx.put_u32(n_payors);
-        tx.put_u32(n_payees);
-        for user_id in user_set{
-            tx.put_u32(user_id);
-            if count<n_payors{
-                let amount = self._generate_random(0,
-                                cmp::min(MAX_DEPOSIT,
-                                    self.small_bank.get_checking_amount(user_id)
-                                )
-                            );
-                tx.put_u32(amount);
-                amount_to_split += amount;
+        // Sample distinct users, in a deterministic order: the first ones pay, the others receive.
+        let mut users: Vec<u32> = vec![self._sample_user() as u32];
+        let cross_shard = self._sample_cross_shard();
+        while users.len() < party_size as usize{
+            let user_id = self._sample_counterparty(users[0], cross_shard, &users);
+            if !users.contains(&user_id){
+                users.push(user_id);
             }
-            else{
-                tx.put_u32(amount_to_split/n_payees);
-                if count==party_size-1{
-                    tx.put_u32(amount_to_split - ((n_payees-1)*(amount_to_split/n_payees)));
-                }
-            }
-            count += 1;
-        }
-    }
-
-    fn _get_split_tx_dependency(&self, tx: Bytes) -> Vec<u32>{        
-        let mut dependency: Vec<u32> = Vec::new();
-        let mut start_byte = TX_DATA_BYTE;
-
-        let n_payors: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        let n_payees: u32 = self._get_bytes_to_u32(&tx[start_byte+4..start_byte+8]);
-        start_byte = start_byte+8;
-
-        for _ in 0..n_payors{
-            let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-            dependency.push(user_id);
-            start_byte += 8;
         }
+        let (payor_ids, payee_ids) = users.split_at(n_payors as usize);
 
-        for _ in 0..n_payees{
-            let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-            dependency.push(user_id);
-            start_byte += 8;
+        let mut amount_to_split: u32 = 0;
+        let mut payors: Vec<(u32, u32)> = Vec::new();
+        for user_id in payor_ids{
+            let amount = self._generate_random(0,
+                            cmp::min(MAX_DEPOSIT,
+                                self.small_bank.get_checking_amount(*user_id)
+                            )
+                        );
+            payors.push((*user_id, amount));
+            amount_to_split += amount;
         }
 
-        return dependency;
-    }
-
-    fn _execute_tx_split(&mut self, tx: Bytes){
-        let mut start_byte = TX_DATA_BYTE;
-        let n_payors: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        let n_payees: u32 = self._get_bytes_to_u32(&tx[start_byte+4..start_byte+8]);
-        start_byte = start_byte+8;
-
-        for _ in 0..n_payors{
-            let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-            start_byte += 4;
-            let amount: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-            start_byte += 4;
-
-            self.small_bank.withdraw_checking(user_id, amount);
+        // Every payee gets an equal share, the last one also gets the remainder.
+        let share = amount_to_split/n_payees;
+        let mut payees: Vec<(u32, u32)> = payee_ids.iter().map(|user_id| (*user_id, share)).collect();
+        if let Some((_, amount)) = payees.last_mut(){
+            *amount += amount_to_split - share*n_payees;
         }
 
-        for _ in 0..n_payees{
-            let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-            start_byte += 4;
-            let amount: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-            start_byte += 4;
-
-            self.small_bank.deposit_checking(user_id, amount);
-        }
+        return SmallBankTx::Split{payors: payors, payees: payees};
     }
 
     /// Transaction type: 5
-    fn _generate_tx_amalgamate(&self, tx: &mut BytesMut){
+    fn _generate_tx_amalgamate(&mut self) -> SmallBankTx{
         // amalgamate
         let user_id: u32 = self._sample_user() as u32;
-        // Update tx
-        tx.put_u32(user_id);
-    }
-
-    fn _get_amalgamate_tx_dependency(&self, tx: Bytes) -> Vec<u32>{
-        let mut dependency: Vec<u32> = Vec::new();
-        let start_byte = TX_DATA_BYTE;
-
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        dependency.push(user_id);
-
-        return dependency;
-    }
-
-    fn _execute_tx_amalgamate(&mut self, tx: Bytes){
-        let start_byte = TX_DATA_BYTE;
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        let saving_amount = self.small_bank.get_saving_amount(user_id);
-
-        if  saving_amount<= MAX_AMOUNT-self.small_bank.get_checking_amount(user_id){
-            self.small_bank.withdraw_saving(user_id, saving_amount);
-            self.small_bank.deposit_checking(user_id, saving_amount);
-        }
+        return SmallBankTx::Amalgamate{user: user_id};
     }
 
     /// Transaction type: 6
-    fn _generate_tx_read(&self, tx: &mut BytesMut){
+    fn _generate_tx_read(&mut self) -> SmallBankTx{
         // Read
         let user_id: u32 = self._sample_user() as u32;
-        // Update tx
-        tx.put_u32(user_id);
-    }
-
-    fn _get_read_tx_dependency(&self, tx: Bytes) -> Vec<u32>{
-        let mut dependency: Vec<u32> = Vec::new();
-        let start_byte = TX_DATA_BYTE;
-
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        dependency.push(user_id);
+        return SmallBankTx::Read{user: user_id};
+    }
+
+    fn _generate_transaction(&mut self, tx_id:u8, sample_tx:bool, tx_uid:u64) -> Bytes{
+        let tx = match tx_id{
+            0 => self._generate_tx_deposit_saving(),
+            1 => self._generate_tx_deposit_checking(),
+            2 => self._generate_tx_write_cheque(),
+            3 => self._generate_tx_send(),
+            4 => self._generate_tx_split(),
+            5 => self._generate_tx_amalgamate(),
+            _ => self._generate_tx_read(),
+        };
+
+        // Later transactions are drawn against the balances resulting from this one.
+        if self.balance_tracking == BalanceTracking::Generated{
+            let _ = self._execute_transaction(&tx);
+        }
 
-        return dependency;
+        let header = TxHeader{sample: sample_tx, uid: tx_uid};
+        return tx.encode(header, self.tx_size);
     }
 
-    fn _execute_tx_read(&mut self, tx: Bytes){
-        let start_byte = TX_DATA_BYTE;
-        let user_id: u32 = self._get_bytes_to_u32(&tx[start_byte..start_byte+4]);
-        let _ = self.small_bank.get_checking_amount(user_id);
+    fn _execute_transaction(&mut self, tx: &SmallBankTx) -> ExecutionOutcome{
+        return execute_on(&mut self.small_bank, tx);
     }
+ 
 
-    fn _generate_transaction(&self, tx_id:u8, sample_tx:bool, tx_uid:u64) -> Bytes{
-        let mut tx = BytesMut::with_capacity(self.tx_size);
-
-        // Update first byte with indicator
-        if sample_tx{
-            tx.put_u8(0u8); // Sample txs start with 0.
-        }
-        else{
-            tx.put_u8(1u8); // Standard txs start with 1.
-        }
-
-        // Add unique transaction id to uniquely identify the transaction
-        tx.put_u64(tx_uid);
-
-        // Update second byte with transaction id
-        tx.put_u8(tx_id);
+    pub fn get_next_transaction(&mut self, sample_tx:bool, tx_uid:u64) -> Bytes{
 
-        match tx_id{
-            0 => self._generate_tx_deposit_saving(&mut tx),
-            1 => self._generate_tx_deposit_checking(&mut tx),
-            2 => self._generate_tx_write_cheque(&mut tx),
-            3 => self._generate_tx_send(&mut tx),
-            4 => self._generate_tx_split(&mut tx),
-            5 => self._generate_tx_amalgamate(&mut tx),
-            _ => self._generate_tx_read(&mut tx),
-        }
+        // get transaction id
+        let tx_id:u8 = self._sample_tx_type();
 
-        // Resize transactions
-        tx.resize(self.tx_size, 0u8);
-        let tx_bytes = tx.split().freeze();
+        // Generate transaction
+        return self._generate_transaction(tx_id, sample_tx, tx_uid);
 
-        return tx_bytes;
     }
 
-    fn _get_dependency(&self, tx_id: u8, tx: Bytes) -> (char, Vec<u32>){
-        match tx_id{
-            0 => return ('w', self._get_deposit_saving_tx_dependency(tx)),
-            1 => return ('w', self._get_deposit_checking_tx_dependency(tx)),
-            2 => return ('w', self._get_write_cheque_tx_dependency(tx)),
-            3 => return ('w', self._get_send_tx_dependency(tx)),
-            4 => return ('w', self._get_split_tx_dependency(tx)),
-            5 => return ('w', self._get_amalgamate_tx_dependency(tx)),
-            _ => return ('r', self._get_read_tx_dependency(tx)),
+    /// Decode a transaction received from the network, checking that it fits in `tx_size` bytes and
+    /// only touches existing users.
+    pub fn decode_transaction(&self, tx: &Bytes) -> Result<SmallBankTx, DecodeError>{
+        if tx.len() > self.tx_size{
+            return Err(DecodeError::PayloadOverflow{size: tx.len(), tx_size: self.tx_size});
         }
-    }
-
-    fn _execute_transaction(&mut self, tx_id: u8, tx: Bytes){
-        match tx_id{
-            0 => self._execute_tx_deposit_saving(tx),
-            1 => self._execute_tx_deposit_checking(tx),
-            2 => self._execute_tx_write_cheque(tx),
-            3 => self._execute_tx_send(tx),
-            4 => self._execute_tx_split(tx),
-            5 => self._execute_tx_amalgamate(tx),
-            _ => self._execute_tx_read(tx),
+        let decoded = SmallBankTx::decode(tx)?;
+        let (_, users) = decoded.dependency();
+        if let Some(user) = users.into_iter().find(|user| *user as u64 >= self.n_users){
+            return Err(DecodeError::UnknownUser{user: user, n_users: self.n_users});
         }
+        return Ok(decoded);
     }
- 
-
-    pub fn get_next_transaction(&self, sample_tx:bool, tx_uid:u64) -> Bytes{
-
-        // get transaction id
-        let mut tx_id:u8 =  6;
-        if self._sample_tx_type()==true{
-            tx_id = self._sample_mtx() as u8;
-        }
-
-        // Generate transaction
-        return self._generate_transaction(tx_id, sample_tx, tx_uid);
 
+    pub fn get_transaction_dependency(&self, tx: Bytes) -> Result<(char, Vec<u32>), DecodeError>{
+        let tx = self.decode_transaction(&tx)?;
+        return Ok(tx.dependency());
     }
 
-    pub fn get_transaction_dependency(&self, tx: Bytes) -> (char, Vec<u32>){
-        // get transaction id
-        let tx_id: u8 = tx[9];
 
-        // get transaction dependency vector
-        return self._get_dependency(tx_id, tx);
+    /// Execute a serialized transaction, after validating it.
+    pub fn execute_transaction(&mut self, tx: Bytes) -> Result<ExecutionOutcome, DecodeError>{
+        let tx = self.decode_transaction(&tx)?;
+        return Ok(self._execute_transaction(&tx));
     }
 
+    /// Execute a decoded transaction.
+    pub fn execute(&mut self, tx: &SmallBankTx) -> ExecutionOutcome{
+        return self._execute_transaction(tx);
+    }
 
-    pub fn execute_transaction(&mut self, tx: Bytes){
-        // get transaction id
-        let tx_id: u8 = tx[9];
+    /// Execute an ordered block of serialized transactions with `executor`. The outcomes and final
+    /// balances are the same as executing the transactions one after the other; transactions that fail
+    /// validation are skipped.
+    pub fn execute_block<E: BlockExecutor>(&mut self, executor: &E, block: &[Bytes]) -> Vec<Result<ExecutionOutcome, DecodeError>>{
+        let decoded: Vec<Result<SmallBankTx, DecodeError>> = block.iter().map(|tx| self.decode_transaction(tx)).collect();
+        let valid: Vec<SmallBankTx> = decoded.iter().filter_map(|tx| tx.as_ref().ok().cloned()).collect();
+        let mut outcomes = executor.execute(&mut self.small_bank, &valid).into_iter();
+        return decoded.into_iter().map(|tx| match tx{
+            Ok(_) => Ok(outcomes.next().expect("One outcome per valid transaction")),
+            Err(e) => Err(e),
+        }).collect();
+    }
 
-        // Execute transaction
-        self._execute_transaction(tx_id, tx);
+    /// Execute the sequenced transactions of `shard` that are ready against the balances held by the
+    /// handler, see `DeterministicShard`.
+    pub fn advance_deterministic(&mut self, shard: &mut DeterministicShard) -> Progress{
+        return shard.advance(&mut self.small_bank);
     }
 
-    pub fn get_transaction_uid(&self, tx: Bytes) -> u64{
-        return self._get_bytes_to_u64(&tx[1..9]);
+    pub fn get_transaction_uid(&self, tx: Bytes) -> Result<u64, DecodeError>{
+        return Ok(TxHeader::decode(&tx)?.uid);
     }
 
 }
@@ -0,0 +1,116 @@
+use sha2::{Digest as _, Sha256};
+
+#[cfg(test)]
+#[path = "tests/merkle_tests.rs"]
+pub mod merkle_tests;
+
+/// A SHA-256 digest.
+pub type Digest = [u8; 32];
+
+/// The sibling of the last node of a level with an odd number of nodes.
+const EMPTY: Digest = [0u8; 32];
+
+/// The leaf committing to the balances of a user.
+pub fn account_leaf(user_id: u32, checking: u32, saving: u32) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(user_id.to_be_bytes());
+    hasher.update(checking.to_be_bytes());
+    hasher.update(saving.to_be_bytes());
+    hasher.finalize().into()
+}
+
+fn parent(left: &Digest, right: &Digest) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// A binary Merkle tree over a fixed number of leaves, updated incrementally: changing a leaf only
+/// recomputes the nodes on its path to the root.
+#[derive(Clone, Debug)]
+pub struct MerkleTree {
+    // The nodes of every level, from the leaves to the root.
+    levels: Vec<Vec<Digest>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: Vec<Digest>) -> Self {
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| parent(&pair[0], pair.get(1).unwrap_or(&EMPTY)))
+                .collect();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// The root of the tree (`EMPTY` if the tree has no leaf).
+    pub fn root(&self) -> Digest {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or(EMPTY)
+    }
+
+    /// Replace a leaf and update its path to the root.
+    pub fn update(&mut self, index: usize, leaf: Digest) {
+        let mut index = index;
+        self.levels[0][index] = leaf;
+        for level in 1..self.levels.len() {
+            let children = &self.levels[level - 1];
+            let left = &children[index & !1];
+            let right = children.get(index | 1).unwrap_or(&EMPTY);
+            let node = parent(left, right);
+            index /= 2;
+            self.levels[level][index] = node;
+        }
+    }
+
+    /// The siblings of the path from a leaf to the root.
+    pub fn proof(&self, index: usize) -> Vec<Digest> {
+        let mut index = index;
+        let mut siblings = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            siblings.push(level.get(index ^ 1).copied().unwrap_or(EMPTY));
+            index /= 2;
+        }
+        siblings
+    }
+}
+
+/// Proves the balances of a user against a state root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountProof {
+    pub user_id: u32,
+    pub checking: u32,
+    pub saving: u32,
+    /// The position of the account in the tree.
+    pub index: usize,
+    /// The siblings of the path from the account to the root.
+    pub siblings: Vec<Digest>,
+}
+
+impl AccountProof {
+    /// Check that the proof leads to `root`.
+    pub fn verify(&self, root: &Digest) -> bool {
+        let mut index = self.index;
+        let mut node = account_leaf(self.user_id, self.checking, self.saving);
+        for sibling in &self.siblings {
+            node = if index & 1 == 0 {
+                parent(&node, sibling)
+            } else {
+                parent(sibling, &node)
+            };
+            index /= 2;
+        }
+        node == *root
+    }
+}
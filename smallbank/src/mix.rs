@@ -0,0 +1,140 @@
+use rand::distributions::WeightedIndex;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(test)]
+#[path = "tests/mix_tests.rs"]
+pub mod mix_tests;
+
+/// The number of SmallBank transaction types.
+pub const N_TX_TYPES: usize = 7;
+
+/// The name of each transaction type, indexed by type id.
+pub const TX_TYPE_NAMES: [&str; N_TX_TYPES] = [
+    "deposit_saving",
+    "deposit_checking",
+    "write_cheque",
+    "send",
+    "split",
+    "amalgamate",
+    "read",
+];
+
+/// Errors raised when building a transaction mix.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MixError {
+    /// A weight is negative or not finite.
+    InvalidWeight(f64),
+    /// All the weights are zero.
+    Empty,
+    /// The mix refers to a transaction type that does not exist.
+    UnknownType(String),
+    /// The mix is neither a preset nor a list of `<type>=<weight>` pairs.
+    Malformed(String),
+}
+
+impl fmt::Display for MixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MixError::InvalidWeight(weight) => write!(f, "Invalid transaction weight {}", weight),
+            MixError::Empty => write!(
+                f,
+                "At least one transaction type must have a positive weight"
+            ),
+            MixError::UnknownType(name) => write!(
+                f,
+                "Unknown transaction type '{}' (expected one of {})",
+                name,
+                TX_TYPE_NAMES.join(", ")
+            ),
+            MixError::Malformed(mix) => write!(f, "Malformed transaction mix '{}'", mix),
+        }
+    }
+}
+
+impl std::error::Error for MixError {}
+
+/// The relative frequency of each transaction type in the generated workload. Weights are indexed
+/// by transaction type id and do not need to sum to any particular value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionMix {
+    weights: [f64; N_TX_TYPES],
+}
+
+impl TransactionMix {
+    pub fn new(weights: [f64; N_TX_TYPES]) -> Result<Self, MixError> {
+        if let Some(weight) = weights.iter().find(|x| !x.is_finite() || **x < 0.0) {
+            return Err(MixError::InvalidWeight(*weight));
+        }
+        if weights.iter().all(|x| *x == 0.0) {
+            return Err(MixError::Empty);
+        }
+        Ok(Self { weights })
+    }
+
+    /// The legacy mix: a read with probability `1 - prob_choose_mtx`, otherwise one of the six
+    /// modifying transactions chosen uniformly.
+    pub fn from_prob_choose_mtx(prob_choose_mtx: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&prob_choose_mtx),
+            "The probability of modifying transactions must be between 0 and 1"
+        );
+        let modifying = prob_choose_mtx / (N_TX_TYPES - 1) as f64;
+        let mut weights = [modifying; N_TX_TYPES];
+        weights[N_TX_TYPES - 1] = 1.0 - prob_choose_mtx;
+        Self { weights }
+    }
+
+    /// Standard mixes:
+    /// - `oltpbench`: the SmallBank mix of OLTP-Bench (no split), 25% send and 15% of each other type;
+    /// - `uniform`: every transaction type is equally likely;
+    /// - `read_only`: only reads;
+    /// - `write_only`: the six modifying transactions, uniformly.
+    pub fn preset(name: &str) -> Option<Self> {
+        let weights = match name {
+            "oltpbench" => [15.0, 15.0, 15.0, 25.0, 0.0, 15.0, 15.0],
+            "uniform" => [1.0; N_TX_TYPES],
+            "read_only" => [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            "write_only" => [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0],
+            _ => return None,
+        };
+        Some(Self { weights })
+    }
+
+    /// The weight of a transaction type.
+    pub fn weight(&self, tx_type: u8) -> f64 {
+        self.weights[tx_type as usize]
+    }
+
+    /// The distribution of transaction type ids described by the mix.
+    pub fn distribution(&self) -> WeightedIndex<f64> {
+        WeightedIndex::new(self.weights).expect("Transaction mix has a positive weight")
+    }
+}
+
+impl FromStr for TransactionMix {
+    type Err = MixError;
+
+    /// Parse either a preset name, or a comma-separated list of `<type>=<weight>` pairs (for instance
+    /// `deposit_saving=10,send=40,split=5`); unlisted types have weight zero.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(mix) = Self::preset(s) {
+            return Ok(mix);
+        }
+        let mut weights = [0.0; N_TX_TYPES];
+        for pair in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (name, weight) = pair
+                .split_once('=')
+                .ok_or_else(|| MixError::Malformed(s.to_string()))?;
+            let index = TX_TYPE_NAMES
+                .iter()
+                .position(|x| *x == name.trim())
+                .ok_or_else(|| MixError::UnknownType(name.trim().to_string()))?;
+            weights[index] = weight
+                .trim()
+                .parse()
+                .map_err(|_| MixError::Malformed(s.to_string()))?;
+        }
+        Self::new(weights)
+    }
+}
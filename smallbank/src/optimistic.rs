@@ -0,0 +1,227 @@
+use crate::executor::{thread_pool, BlockExecutor};
+use crate::state::{execute_on, Account, AccountError, AccountState, ExecutionOutcome};
+use crate::transaction::SmallBankTx;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[cfg(test)]
+#[path = "tests/optimistic_tests.rs"]
+pub mod optimistic_tests;
+
+type Key = (Account, u32);
+
+/// The number of independently locked parts of the multi-version memory.
+const N_STRIPES: usize = 64;
+
+/// Executes blocks of transactions optimistically, in the style of Block-STM, without looking at
+/// their declared dependencies.
+///
+/// Transactions run speculatively in parallel against a multi-version memory holding the balances
+/// written by every transaction of the block: a transaction reads the latest write of the
+/// transactions preceding it. Once every pending transaction has run, the read sets are validated
+/// and the transactions that read a stale balance are executed again. The lowest invalid transaction
+/// only depends on valid transactions, so it is valid after its re-execution and the executor
+/// reaches the serial result after at most one round per transaction.
+pub struct OptimisticExecutor {
+    pool: ThreadPool,
+    // The total number of transaction executions, including re-executions.
+    executions: AtomicUsize,
+}
+
+impl OptimisticExecutor {
+    pub fn new(n_threads: usize) -> Self {
+        Self {
+            pool: thread_pool(n_threads),
+            executions: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of transaction executions so far, including the re-executions caused by conflicts.
+    pub fn executions(&self) -> usize {
+        self.executions.load(Ordering::Relaxed)
+    }
+}
+
+impl BlockExecutor for OptimisticExecutor {
+    fn execute<S: AccountState + Sync + ?Sized>(
+        &self,
+        state: &mut S,
+        block: &[SmallBankTx],
+    ) -> Vec<ExecutionOutcome> {
+        let base: &S = state;
+        let memory = MultiVersionMemory::new();
+        let mut incarnations: Vec<Option<Incarnation>> = vec![None; block.len()];
+        let mut pending: Vec<usize> = (0..block.len()).collect();
+
+        while let Some(lowest) = pending.first().copied() {
+            let results: Vec<_> = self.pool.install(|| {
+                pending
+                    .par_iter()
+                    .map(|index| {
+                        let incarnation = Incarnation::run(base, &memory, *index, &block[*index]);
+                        if let Some(previous) = &incarnations[*index] {
+                            for (key, _) in &previous.writes {
+                                memory.remove(*key, *index);
+                            }
+                        }
+                        for (key, amount) in &incarnation.writes {
+                            memory.write(*key, *index, *amount);
+                        }
+                        (*index, incarnation)
+                    })
+                    .collect()
+            });
+            self.executions.fetch_add(results.len(), Ordering::Relaxed);
+            for (index, incarnation) in results {
+                incarnations[index] = Some(incarnation);
+            }
+
+            // Transactions before the lowest re-executed one read the same balances as before.
+            pending = self.pool.install(|| {
+                (lowest..block.len())
+                    .into_par_iter()
+                    .filter(|index| {
+                        let incarnation = incarnations[*index].as_ref().expect("Executed");
+                        !incarnation.is_valid(base, &memory, *index)
+                    })
+                    .collect()
+            });
+        }
+
+        for (key, amount) in memory.latest() {
+            state.set_balance(key.0, key.1, amount);
+        }
+        incarnations
+            .into_iter()
+            .map(|x| x.expect("Executed").outcome)
+            .collect()
+    }
+}
+
+/// The balances written by the transactions of a block, indexed by transaction.
+struct MultiVersionMemory {
+    stripes: Vec<Mutex<HashMap<Key, BTreeMap<usize, u32>>>>,
+}
+
+impl MultiVersionMemory {
+    fn new() -> Self {
+        Self {
+            stripes: (0..N_STRIPES).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    fn stripe(&self, key: Key) -> &Mutex<HashMap<Key, BTreeMap<usize, u32>>> {
+        &self.stripes[key.1 as usize % N_STRIPES]
+    }
+
+    /// The latest balance written by a transaction preceding `index`.
+    fn read(&self, key: Key, index: usize) -> Option<u32> {
+        let stripe = self.stripe(key).lock().unwrap();
+        let versions = stripe.get(&key)?;
+        versions.range(..index).next_back().map(|(_, x)| *x)
+    }
+
+    fn write(&self, key: Key, index: usize, amount: u32) {
+        let mut stripe = self.stripe(key).lock().unwrap();
+        stripe.entry(key).or_default().insert(index, amount);
+    }
+
+    fn remove(&self, key: Key, index: usize) {
+        let mut stripe = self.stripe(key).lock().unwrap();
+        if let Some(versions) = stripe.get_mut(&key) {
+            versions.remove(&index);
+        }
+    }
+
+    /// The final balance of every written account, sorted by account.
+    fn latest(self) -> Vec<(Key, u32)> {
+        let mut latest: Vec<_> = self
+            .stripes
+            .into_iter()
+            .flat_map(|x| x.into_inner().unwrap())
+            .filter_map(|(key, versions)| versions.values().next_back().map(|x| (key, *x)))
+            .collect();
+        latest.sort();
+        latest
+    }
+}
+
+/// One execution of a transaction, with the balances it read and wrote.
+#[derive(Clone)]
+struct Incarnation {
+    outcome: ExecutionOutcome,
+    reads: Vec<(Key, Result<u32, AccountError>)>,
+    writes: Vec<(Key, u32)>,
+}
+
+impl Incarnation {
+    fn run<S: AccountState + ?Sized>(
+        base: &S,
+        memory: &MultiVersionMemory,
+        index: usize,
+        tx: &SmallBankTx,
+    ) -> Self {
+        let mut view = SpeculativeView {
+            base,
+            memory,
+            index,
+            reads: RefCell::default(),
+            writes: Vec::new(),
+        };
+        let outcome = execute_on(&mut view, tx);
+        Self {
+            outcome,
+            reads: view.reads.into_inner(),
+            writes: view.writes,
+        }
+    }
+
+    /// Check that the balances read by the execution are still the ones preceding the transaction.
+    fn is_valid<S: AccountState + ?Sized>(
+        &self,
+        base: &S,
+        memory: &MultiVersionMemory,
+        index: usize,
+    ) -> bool {
+        self.reads.iter().all(|(key, read)| {
+            let current = match memory.read(*key, index) {
+                Some(amount) => Ok(amount),
+                None => base.balance(key.0, key.1),
+            };
+            current == *read
+        })
+    }
+}
+
+/// The state seen by a transaction: its own writes, then the writes of the preceding transactions,
+/// then the balances before the block.
+struct SpeculativeView<'a, S: ?Sized> {
+    base: &'a S,
+    memory: &'a MultiVersionMemory,
+    index: usize,
+    reads: RefCell<Vec<(Key, Result<u32, AccountError>)>>,
+    writes: Vec<(Key, u32)>,
+}
+
+impl<S: AccountState + ?Sized> AccountState for SpeculativeView<'_, S> {
+    fn balance(&self, account: Account, user_id: u32) -> Result<u32, AccountError> {
+        let key = (account, user_id);
+        if let Some((_, amount)) = self.writes.iter().rev().find(|(x, _)| *x == key) {
+            return Ok(*amount);
+        }
+        let read = match self.memory.read(key, self.index) {
+            Some(amount) => Ok(amount),
+            None => self.base.balance(account, user_id),
+        };
+        self.reads.borrow_mut().push((key, read));
+        read
+    }
+
+    fn set_balance(&mut self, account: Account, user_id: u32, amount: u32) {
+        self.writes.push(((account, user_id), amount));
+    }
+}
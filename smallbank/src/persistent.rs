@@ -0,0 +1,180 @@
+use crate::state::{
+    execute_on, Account, AccountError, AccountState, ExecutionOutcome, INITIAL_BALANCE,
+};
+use crate::transaction::SmallBankTx;
+use crate::SmallBank;
+use std::collections::HashMap;
+use std::fmt;
+use store::{Store, StoreError};
+
+#[cfg(test)]
+#[path = "tests/persistent_tests.rs"]
+pub mod persistent_tests;
+
+/// Errors raised when reading or writing the persisted accounts.
+#[derive(Debug)]
+pub enum PersistenceError {
+    Store(StoreError),
+    /// A persisted balance is not 4 bytes long.
+    CorruptedBalance {
+        account: Account,
+        user_id: u32,
+        len: usize,
+    },
+}
+
+impl fmt::Display for PersistenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PersistenceError::Store(e) => write!(f, "Store error: {}", e),
+            PersistenceError::CorruptedBalance {
+                account,
+                user_id,
+                len,
+            } => write!(
+                f,
+                "Corrupted {:?} balance of user {}: {} bytes instead of 4",
+                account, user_id, len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PersistenceError {}
+
+impl From<StoreError> for PersistenceError {
+    fn from(e: StoreError) -> Self {
+        PersistenceError::Store(e)
+    }
+}
+
+/// SmallBank accounts persisted in a `Store`, so that a node recovers the balances of its shard after
+/// a crash. Accounts never written to the store hold `INITIAL_BALANCE`. The balances may also be
+/// cached in memory, in which case they are all read from the store when the accounts are opened.
+///
+/// The balances updated by a transaction are written to the store in a single atomic batch: a crash
+/// persists either all or none of a transaction.
+pub struct PersistentAccounts {
+    store: Store,
+    n_users: u64,
+    cache: Option<SmallBank>,
+}
+
+impl PersistentAccounts {
+    pub async fn new(store: Store, n_users: u64, cache: bool) -> Result<Self, PersistenceError> {
+        let mut accounts = Self {
+            store,
+            n_users,
+            cache: None,
+        };
+        if cache {
+            let mut small_bank = SmallBank::new(n_users);
+            for user_id in 0..n_users as u32 {
+                for account in [Account::Checking, Account::Saving] {
+                    let balance = accounts.read(account, user_id).await?;
+                    small_bank.set_balance(account, user_id, balance);
+                }
+            }
+            accounts.cache = Some(small_bank);
+        }
+        Ok(accounts)
+    }
+
+    /// The store key of an account.
+    fn key(account: Account, user_id: u32) -> Vec<u8> {
+        let mut key = match account {
+            Account::Checking => b"checking/".to_vec(),
+            Account::Saving => b"saving/".to_vec(),
+        };
+        key.extend_from_slice(&user_id.to_be_bytes());
+        key
+    }
+
+    /// Read the balance of an existing account from the store.
+    async fn read(&mut self, account: Account, user_id: u32) -> Result<u32, PersistenceError> {
+        let value = self.store.read(Self::key(account, user_id)).await?;
+        match value {
+            Some(bytes) => match bytes.as_slice().try_into() {
+                Ok(balance) => Ok(u32::from_be_bytes(balance)),
+                Err(_) => Err(PersistenceError::CorruptedBalance {
+                    account,
+                    user_id,
+                    len: bytes.len(),
+                }),
+            },
+            None => Ok(INITIAL_BALANCE),
+        }
+    }
+
+    /// The balance of an account, or `None` if the account does not exist.
+    pub async fn balance(
+        &mut self,
+        account: Account,
+        user_id: u32,
+    ) -> Result<Option<u32>, PersistenceError> {
+        if user_id as u64 >= self.n_users {
+            return Ok(None);
+        }
+        match &self.cache {
+            Some(cache) => Ok(cache.balance(account, user_id).ok()),
+            None => self.read(account, user_id).await.map(Some),
+        }
+    }
+
+    /// Execute a transaction and persist the balances it updates. The outcome is only returned once the
+    /// store wrote them; if the write fails, neither the store nor the cache hold any of the updates.
+    pub async fn execute(&mut self, tx: &SmallBankTx) -> Result<ExecutionOutcome, PersistenceError> {
+        // Execute the transaction on a snapshot of the accounts it touches.
+        let mut snapshot = Snapshot::default();
+        let (_, users) = tx.dependency();
+        for user_id in users {
+            for account in [Account::Checking, Account::Saving] {
+                if let Some(balance) = self.balance(account, user_id).await? {
+                    snapshot.balances.insert((account, user_id), balance);
+                }
+            }
+        }
+        let outcome = execute_on(&mut snapshot, tx);
+
+        // Persist the updated balances at once (the snapshot is only updated if the transaction
+        // commits).
+        if snapshot.updated.is_empty() {
+            return Ok(outcome);
+        }
+        let entries = snapshot
+            .updated
+            .iter()
+            .map(|((account, user_id), balance)| {
+                (Self::key(*account, *user_id), balance.to_be_bytes().to_vec())
+            })
+            .collect();
+        self.store.write_batch(entries).await?;
+        if let Some(cache) = self.cache.as_mut() {
+            for ((account, user_id), balance) in snapshot.updated {
+                cache.set_balance(account, user_id, balance);
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+/// The balances of the accounts touched by a transaction, and the ones it updates.
+#[derive(Default)]
+struct Snapshot {
+    balances: HashMap<(Account, u32), u32>,
+    updated: Vec<((Account, u32), u32)>,
+}
+
+impl AccountState for Snapshot {
+    fn balance(&self, account: Account, user_id: u32) -> Result<u32, AccountError> {
+        self.balances
+            .get(&(account, user_id))
+            .copied()
+            .ok_or(AccountError::Unknown)
+    }
+
+    fn set_balance(&mut self, account: Account, user_id: u32, amount: u32) {
+        self.balances.insert((account, user_id), amount);
+        self.updated.push(((account, user_id), amount));
+    }
+}
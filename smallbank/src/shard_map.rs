@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+#[cfg(test)]
+#[path = "tests/shard_map_tests.rs"]
+pub mod shard_map_tests;
+
+pub type UserId = u64;
+pub type ShardId = u32;
+
+/// Number of points each shard owns on the consistent-hashing ring.
+const VIRTUAL_NODES: u32 = 64;
+
+/// Decides which shard owns each SmallBank user.
+pub trait ShardMap: Send + Sync {
+    /// The total number of shards.
+    fn num_shards(&self) -> u32;
+
+    /// The shard owning the specified user.
+    fn shard(&self, user_id: UserId) -> ShardId;
+
+    /// The rank of a user among the users of its shard, in increasing order of id. Placements that can
+    /// compute it arithmetically return it for every user; the others return `None` for every user.
+    fn index_in_shard(&self, _user_id: UserId) -> Option<u64> {
+        None
+    }
+}
+
+/// Places user `u` on shard `u % num_shards`.
+#[derive(Clone, Debug)]
+pub struct ModuloShardMap {
+    num_shards: u32,
+}
+
+impl ModuloShardMap {
+    pub fn new(num_shards: u32) -> Self {
+        assert!(num_shards > 0, "There must be at least one shard");
+        Self { num_shards }
+    }
+}
+
+impl ShardMap for ModuloShardMap {
+    fn num_shards(&self) -> u32 {
+        self.num_shards
+    }
+
+    fn shard(&self, user_id: UserId) -> ShardId {
+        (user_id % self.num_shards as UserId) as ShardId
+    }
+
+    fn index_in_shard(&self, user_id: UserId) -> Option<u64> {
+        Some(user_id / self.num_shards as UserId)
+    }
+}
+
+/// Splits the users into contiguous ranges: shard `i` owns the users from `starts[i]` (included) to
+/// `starts[i+1]` (excluded), and the last shard owns all the users after its start.
+#[derive(Clone, Debug)]
+pub struct RangeShardMap {
+    starts: Vec<UserId>,
+}
+
+impl RangeShardMap {
+    pub fn new(starts: Vec<UserId>) -> Self {
+        assert!(
+            starts.first() == Some(&0),
+            "The first shard must start at user 0"
+        );
+        assert!(
+            starts.windows(2).all(|w| w[0] < w[1]),
+            "Shard ranges must be non-empty and sorted"
+        );
+        Self { starts }
+    }
+
+    /// Split `n_users` users into `num_shards` ranges of (almost) equal size.
+    pub fn uniform(n_users: u64, num_shards: u32) -> Self {
+        assert!(num_shards > 0, "There must be at least one shard");
+        assert!(
+            n_users >= num_shards as u64,
+            "There must be at least one user per shard"
+        );
+        let starts = (0..num_shards as u64)
+            .map(|i| i * n_users / num_shards as u64)
+            .collect();
+        Self::new(starts)
+    }
+}
+
+impl ShardMap for RangeShardMap {
+    fn num_shards(&self) -> u32 {
+        self.starts.len() as u32
+    }
+
+    fn shard(&self, user_id: UserId) -> ShardId {
+        // The number of ranges starting at or before the user (at least 1 since `starts[0] == 0`).
+        let owner = self.starts.partition_point(|start| *start <= user_id);
+        (owner - 1) as ShardId
+    }
+
+    fn index_in_shard(&self, user_id: UserId) -> Option<u64> {
+        Some(user_id - self.starts[self.shard(user_id) as usize])
+    }
+}
+
+/// Places users on a consistent-hashing ring where each shard owns `VIRTUAL_NODES` points. Adding a
+/// shard only moves the users it takes over from the others.
+#[derive(Clone, Debug)]
+pub struct ConsistentHashShardMap {
+    num_shards: u32,
+    /// The points of the ring, sorted by hash.
+    ring: Vec<(u64, ShardId)>,
+}
+
+impl ConsistentHashShardMap {
+    pub fn new(num_shards: u32) -> Self {
+        assert!(num_shards > 0, "There must be at least one shard");
+        let mut ring: Vec<_> = (0..num_shards)
+            .flat_map(|shard| {
+                (0..VIRTUAL_NODES).map(move |i| {
+                    let point = ((shard as u64) << 32) | i as u64;
+                    (hash(point ^ 0x5348_4152_445f_4d41), shard)
+                })
+            })
+            .collect();
+        ring.sort_unstable();
+        Self { num_shards, ring }
+    }
+}
+
+impl ShardMap for ConsistentHashShardMap {
+    fn num_shards(&self) -> u32 {
+        self.num_shards
+    }
+
+    fn shard(&self, user_id: UserId) -> ShardId {
+        let key = hash(user_id);
+        let index = self.ring.partition_point(|(point, _)| *point < key);
+        self.ring[index % self.ring.len()].1
+    }
+}
+
+/// Places users according to an explicit table. Users missing from the table are placed by modulo.
+#[derive(Clone, Debug)]
+pub struct LookupShardMap {
+    table: HashMap<UserId, ShardId>,
+    fallback: ModuloShardMap,
+}
+
+impl LookupShardMap {
+    pub fn new(table: HashMap<UserId, ShardId>, num_shards: u32) -> Self {
+        assert!(
+            table.values().all(|shard| *shard < num_shards),
+            "The lookup table references an unknown shard"
+        );
+        Self {
+            table,
+            fallback: ModuloShardMap::new(num_shards),
+        }
+    }
+}
+
+impl ShardMap for LookupShardMap {
+    fn num_shards(&self) -> u32 {
+        self.fallback.num_shards()
+    }
+
+    fn shard(&self, user_id: UserId) -> ShardId {
+        match self.table.get(&user_id) {
+            Some(shard) => *shard,
+            None => self.fallback.shard(user_id),
+        }
+    }
+}
+
+/// A stable 64-bit mixing function (SplitMix64 finalizer); unlike the std hasher, its output does not
+/// change across runs or Rust versions.
+fn hash(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
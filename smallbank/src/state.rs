@@ -0,0 +1,138 @@
+use crate::transaction::SmallBankTx;
+
+/// The balance of every account when the bank opens.
+pub const INITIAL_BALANCE: u32 = 1000;
+
+/// The two accounts of every SmallBank user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Account {
+    Checking,
+    Saving,
+}
+
+/// The outcome of the execution of a transaction. Transactions either apply all their updates, or
+/// leave every balance untouched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    Committed,
+    /// A balance would become negative.
+    InsufficientFunds,
+    /// A balance would exceed the maximum amount.
+    Overflow,
+    /// The transaction touches an account that does not exist.
+    UnknownAccount,
+    /// The transaction touches an account held by another shard.
+    NotOwned,
+}
+
+/// Errors raised when accessing an account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountError {
+    /// The account does not exist.
+    Unknown,
+    /// The account is held by another shard.
+    NotOwned,
+}
+
+impl From<AccountError> for ExecutionOutcome {
+    fn from(error: AccountError) -> Self {
+        match error {
+            AccountError::Unknown => ExecutionOutcome::UnknownAccount,
+            AccountError::NotOwned => ExecutionOutcome::NotOwned,
+        }
+    }
+}
+
+/// Where the balances of the accounts live.
+pub trait AccountState {
+    /// The balance of an account.
+    fn balance(&self, account: Account, user_id: u32) -> Result<u32, AccountError>;
+
+    /// Overwrite the balance of an existing account.
+    fn set_balance(&mut self, account: Account, user_id: u32, amount: u32);
+}
+
+/// Atomically add signed amounts to accounts: either every update is applied, or none of them if an
+/// account does not exist or a balance would become negative or overflow.
+pub fn apply_updates<S: AccountState + ?Sized>(
+    state: &mut S,
+    updates: &[(Account, u32, i64)],
+) -> ExecutionOutcome {
+    // Compute the resulting balances first, merging the updates of the same account.
+    let mut balances: Vec<((Account, u32), i64)> = Vec::new();
+    for (account, user_id, delta) in updates {
+        let key = (*account, *user_id);
+        let index = match balances.iter().position(|(x, _)| *x == key) {
+            Some(index) => index,
+            None => match state.balance(*account, *user_id) {
+                Ok(balance) => {
+                    balances.push((key, balance as i64));
+                    balances.len() - 1
+                }
+                Err(e) => return e.into(),
+            },
+        };
+        balances[index].1 += delta;
+    }
+
+    for (_, balance) in &balances {
+        if *balance < 0 {
+            return ExecutionOutcome::InsufficientFunds;
+        }
+        if *balance > u32::MAX as i64 {
+            return ExecutionOutcome::Overflow;
+        }
+    }
+
+    for ((account, user_id), balance) in balances {
+        state.set_balance(account, user_id, balance as u32);
+    }
+    ExecutionOutcome::Committed
+}
+
+/// Execute a transaction against the specified account state.
+pub fn execute_on<S: AccountState + ?Sized>(state: &mut S, tx: &SmallBankTx) -> ExecutionOutcome {
+    let updates = match tx {
+        SmallBankTx::DepositSaving { user, amount } => {
+            vec![(Account::Saving, *user, *amount as i64)]
+        }
+        SmallBankTx::DepositChecking { user, amount } => {
+            vec![(Account::Checking, *user, *amount as i64)]
+        }
+        SmallBankTx::WriteCheque { user, amount } => {
+            vec![(Account::Checking, *user, -(*amount as i64))]
+        }
+        SmallBankTx::SendPayment { from, to, amount } => vec![
+            (Account::Checking, *from, -(*amount as i64)),
+            (Account::Checking, *to, *amount as i64),
+        ],
+        SmallBankTx::Split { payors, payees } => payors
+            .iter()
+            .map(|(user, amount)| (Account::Checking, *user, -(*amount as i64)))
+            .chain(
+                payees
+                    .iter()
+                    .map(|(user, amount)| (Account::Checking, *user, *amount as i64)),
+            )
+            .collect(),
+        SmallBankTx::Amalgamate { user } => {
+            // Move the whole saving account to the checking account.
+            let saving = match state.balance(Account::Saving, *user) {
+                Ok(amount) => amount as i64,
+                Err(e) => return e.into(),
+            };
+            vec![
+                (Account::Saving, *user, -saving),
+                (Account::Checking, *user, saving),
+            ]
+        }
+        SmallBankTx::Read { user } => {
+            // Reads only check that the account exists: they update nothing.
+            return match state.balance(Account::Checking, *user) {
+                Ok(_) => ExecutionOutcome::Committed,
+                Err(e) => e.into(),
+            };
+        }
+    };
+    apply_updates(state, &updates)
+}
@@ -0,0 +1,212 @@
+use super::*;
+use crate::{
+    ModuloShardMap, SmallBank, SmallBankTransactionHandler, TransactionMix, INITIAL_BALANCE,
+};
+
+const TX_SIZE: usize = 64;
+const N_USERS: u64 = 60;
+const N_SHARDS: u32 = 3;
+
+fn shard_map() -> Arc<dyn ShardMap> {
+    Arc::new(ModuloShardMap::new(N_SHARDS))
+}
+
+/// Advance every shard and deliver the reads they send, until no shard makes progress. Returns the
+/// outcomes reported by each shard.
+fn run(
+    shards: &mut [DeterministicShard],
+    handlers: &mut [SmallBankTransactionHandler],
+) -> Vec<Vec<(Sequence, ExecutionOutcome)>> {
+    let mut executed = vec![Vec::new(); shards.len()];
+    loop {
+        let mut outbox = Vec::new();
+        for (i, shard) in shards.iter_mut().enumerate() {
+            let progress = handlers[i].advance_deterministic(shard);
+            executed[i].extend(progress.executed);
+            outbox.extend(progress.outbox);
+        }
+        if outbox.is_empty() {
+            return executed;
+        }
+        for (recipients, reads) in outbox {
+            for shard_id in recipients {
+                shards[shard_id as usize].receive(reads.clone());
+            }
+        }
+    }
+}
+
+#[test]
+fn deterministic_execution_matches_serial_execution() {
+    let mix = TransactionMix::preset("uniform").unwrap();
+    let mut generator =
+        SmallBankTransactionHandler::with_seed(TX_SIZE, N_USERS, 1.2, mix.clone(), 0);
+    generator.set_cross_shard_probability(shard_map(), 0.5);
+    let mut serial = SmallBankTransactionHandler::with_seed(TX_SIZE, N_USERS, 1.2, mix.clone(), 1);
+    let mut handlers: Vec<_> = (0..N_SHARDS)
+        .map(|shard_id| {
+            let mut handler =
+                SmallBankTransactionHandler::with_seed(TX_SIZE, N_USERS, 1.2, mix.clone(), 1);
+            handler.set_partition(shard_map(), shard_id);
+            handler
+        })
+        .collect();
+    let mut shards: Vec<_> = (0..N_SHARDS)
+        .map(|shard_id| DeterministicShard::new(shard_id, shard_map()))
+        .collect();
+
+    let mut expected: Vec<Vec<(Sequence, ExecutionOutcome)>> = vec![Vec::new(); N_SHARDS as usize];
+    for seq in 0..500 {
+        let bytes = generator.get_next_transaction(false, seq);
+        let tx = serial.decode_transaction(&bytes).unwrap();
+        let outcome = serial.execute(&tx);
+        for shard in shards.iter_mut() {
+            if shard.participants(&tx).contains(&shard.shard_id) {
+                expected[shard.shard_id as usize].push((seq, outcome));
+            }
+            shard.sequence(seq, tx.clone());
+        }
+    }
+
+    // Every participant reaches the outcome of the serial execution.
+    assert_eq!(run(&mut shards, &mut handlers), expected);
+    assert!(shards.iter().all(|shard| shard.pending() == 0));
+    for user in 0..N_USERS as u32 {
+        let owner = &handlers[shard_map().shard(user as u64) as usize];
+        assert_eq!(owner.get_balance(user), serial.get_balance(user));
+    }
+}
+
+#[test]
+fn cross_shard_transaction_waits_for_remote_reads() {
+    let mut states: Vec<_> = (0..2)
+        .map(|shard_id| SmallBank::partitioned(4, Arc::new(ModuloShardMap::new(2)), shard_id))
+        .collect();
+    let mut shards: Vec<_> = (0..2)
+        .map(|shard_id| DeterministicShard::new(shard_id, Arc::new(ModuloShardMap::new(2))))
+        .collect();
+    let send = SmallBankTx::SendPayment {
+        from: 0,
+        to: 1,
+        amount: 300,
+    };
+    for shard in shards.iter_mut() {
+        shard.sequence(0, send.clone());
+        shard.sequence(
+            1,
+            SmallBankTx::WriteCheque {
+                user: 0,
+                amount: 800,
+            },
+        );
+    }
+
+    // Shard 0 sends the balance of user 0 and waits for the one of user 1.
+    let progress = shards[0].advance(&mut states[0]);
+    assert!(progress.executed.is_empty());
+    let expected = RemoteReads {
+        seq: 0,
+        shard_id: 0,
+        reads: vec![(Account::Checking, 0, INITIAL_BALANCE)],
+    };
+    assert_eq!(progress.outbox, vec![(vec![1], expected.clone())]);
+
+    // Shard 1 executes once it received the reads of shard 0 (the write cheque is not its own).
+    shards[1].receive(expected);
+    let progress = shards[1].advance(&mut states[1]);
+    assert_eq!(progress.executed, vec![(0, ExecutionOutcome::Committed)]);
+    let (_, reads) = progress.outbox[0].clone();
+    assert_eq!(shards[1].pending(), 0);
+
+    // Shard 0 then executes both transactions: the write cheque runs after the payment.
+    shards[0].receive(reads);
+    let progress = shards[0].advance(&mut states[0]);
+    assert_eq!(
+        progress.executed,
+        vec![
+            (0, ExecutionOutcome::Committed),
+            (1, ExecutionOutcome::InsufficientFunds)
+        ]
+    );
+    assert_eq!(states[0].balance(Account::Checking, 0), Ok(700));
+    assert_eq!(states[1].balance(Account::Checking, 1), Ok(1300));
+}
+
+#[test]
+#[should_panic(expected = "sequenced out of order")]
+fn transactions_are_sequenced_in_order() {
+    let mut shard = DeterministicShard::new(0, shard_map());
+    shard.sequence(2, SmallBankTx::Read { user: 0 });
+    shard.sequence(1, SmallBankTx::Read { user: 0 });
+}
+
+#[test]
+fn only_owners_read_their_accounts() {
+    // Shard 0 holds a full copy of the bank, where user 1 (owned by shard 1) is stale.
+    let mut stale = SmallBank::new(4);
+    stale.set_balance(Account::Checking, 1, 0);
+    let mut owner = SmallBank::partitioned(4, Arc::new(ModuloShardMap::new(2)), 1);
+    let mut shards: Vec<_> = (0..2)
+        .map(|shard_id| DeterministicShard::new(shard_id, Arc::new(ModuloShardMap::new(2))))
+        .collect();
+    let send = SmallBankTx::SendPayment {
+        from: 1,
+        to: 0,
+        amount: 300,
+    };
+    for shard in shards.iter_mut() {
+        shard.sequence(0, send.clone());
+    }
+
+    // Shard 0 only sends the balance of user 0.
+    let progress = shards[0].advance(&mut stale);
+    let expected = RemoteReads {
+        seq: 0,
+        shard_id: 0,
+        reads: vec![(Account::Checking, 0, INITIAL_BALANCE)],
+    };
+    assert_eq!(progress.outbox, vec![(vec![1], expected.clone())]);
+
+    // Both shards execute against the balance of user 1 read by its owner.
+    shards[1].receive(expected);
+    let progress = shards[1].advance(&mut owner);
+    assert_eq!(progress.executed, vec![(0, ExecutionOutcome::Committed)]);
+    shards[0].receive(progress.outbox[0].1.clone());
+    let progress = shards[0].advance(&mut stale);
+    assert_eq!(progress.executed, vec![(0, ExecutionOutcome::Committed)]);
+    assert_eq!(stale.balance(Account::Checking, 0), Ok(1300));
+}
+
+#[test]
+fn duplicate_and_unexpected_reads_are_ignored() {
+    let mut state = SmallBank::partitioned(6, shard_map(), 0);
+    let mut shard = DeterministicShard::new(0, shard_map());
+    let reads = |seq, shard_id, user| RemoteReads {
+        seq,
+        shard_id,
+        reads: vec![(Account::Checking, user, INITIAL_BALANCE)],
+    };
+    shard.sequence(
+        0,
+        SmallBankTx::SendPayment {
+            from: 0,
+            to: 1,
+            amount: 300,
+        },
+    );
+
+    // The reads of shard 2 do not count for a transaction between shards 0 and 1.
+    shard.receive(reads(0, 2, 2));
+    let progress = shard.advance(&mut state);
+    assert!(progress.executed.is_empty());
+
+    // Two copies of the reads of shard 1 are only counted once.
+    shard.receive(reads(0, 1, 1));
+    shard.receive(reads(0, 1, 1));
+    let progress = shard.advance(&mut state);
+    assert_eq!(progress.executed, vec![(0, ExecutionOutcome::Committed)]);
+
+    // Late copies of the reads of an executed transaction are dropped.
+    shard.receive(reads(0, 1, 1));
+    assert!(shard.remote.is_empty());
+}
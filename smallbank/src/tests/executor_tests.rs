@@ -0,0 +1,102 @@
+use super::*;
+use crate::{SmallBankTransactionHandler, TransactionMix, TxHeader};
+use bytes::Bytes;
+
+const TX_SIZE: usize = 64;
+const N_USERS: u64 = 50;
+
+#[test]
+fn schedule_separates_conflicts() {
+    let block = vec![
+        SmallBankTx::Read { user: 1 },
+        SmallBankTx::Read { user: 1 },
+        SmallBankTx::SendPayment {
+            from: 1,
+            to: 2,
+            amount: 5,
+        },
+        SmallBankTx::DepositChecking { user: 3, amount: 5 },
+        SmallBankTx::Read { user: 2 },
+        SmallBankTx::Amalgamate { user: 3 },
+    ];
+    assert_eq!(
+        ParallelExecutor::schedule(&block),
+        vec![vec![0, 1, 3], vec![2, 5], vec![4]]
+    );
+}
+
+/// A seeded handler with a skewed user distribution, so that blocks have many conflicts.
+fn handler(seed: u64) -> SmallBankTransactionHandler {
+    let mix = TransactionMix::preset("uniform").unwrap();
+    let mut handler = SmallBankTransactionHandler::with_seed(TX_SIZE, N_USERS, 1.5, mix, seed);
+    handler.enable_state_commitment();
+    handler
+}
+
+#[test]
+fn parallel_execution_matches_serial_execution() {
+    let executor = ParallelExecutor::new(4);
+    let mut generator = handler(0);
+    let (mut serial, mut parallel) = (handler(1), handler(1));
+    for block in 0..20 {
+        let block: Vec<Bytes> = (0..100)
+            .map(|i| generator.get_next_transaction(false, block * 100 + i))
+            .collect();
+        let expected: Vec<_> = block
+            .iter()
+            .map(|tx| serial.execute_transaction(tx.clone()))
+            .collect();
+        assert_eq!(parallel.execute_block(&executor, &block), expected);
+    }
+    assert_eq!(parallel.state_root(), serial.state_root());
+}
+
+#[test]
+fn invalid_transactions_are_skipped() {
+    let executor = ParallelExecutor::new(2);
+    let mut handler = handler(0);
+    let header = TxHeader {
+        sample: false,
+        uid: 0,
+    };
+    let block = vec![
+        SmallBankTx::WriteCheque {
+            user: 0,
+            amount: 600,
+        }
+        .encode(header, TX_SIZE),
+        SmallBankTx::Read {
+            user: N_USERS as u32,
+        }
+        .encode(header, TX_SIZE),
+        SmallBankTx::WriteCheque {
+            user: 0,
+            amount: 600,
+        }
+        .encode(header, TX_SIZE),
+    ];
+    let outcomes = handler.execute_block(&executor, &block);
+    assert_eq!(outcomes[0], Ok(ExecutionOutcome::Committed));
+    assert!(outcomes[1].is_err());
+    assert_eq!(outcomes[2], Ok(ExecutionOutcome::InsufficientFunds));
+}
+
+#[test]
+fn reads_do_not_write() {
+    let bank = crate::SmallBank::new(N_USERS);
+    let mut overlay = Overlay {
+        base: &bank,
+        writes: Vec::new(),
+    };
+    let read = SmallBankTx::Read { user: 1 };
+    assert_eq!(execute_on(&mut overlay, &read), ExecutionOutcome::Committed);
+    assert!(overlay.writes.is_empty());
+
+    let unknown = SmallBankTx::Read {
+        user: N_USERS as u32,
+    };
+    assert_eq!(
+        execute_on(&mut overlay, &unknown),
+        ExecutionOutcome::UnknownAccount
+    );
+}
@@ -0,0 +1,377 @@
+use super::*;
+use rand::rngs::StdRng;
+
+const TX_SIZE: usize = 64;
+const N_USERS: u64 = 20;
+
+fn write_only() -> TransactionMix {
+    TransactionMix::preset("write_only").unwrap()
+}
+
+/// Check that every withdrawal of the transaction is covered by the checking balances of `bank`.
+fn is_covered(bank: &SmallBank, tx: &SmallBankTx) -> bool {
+    match tx {
+        SmallBankTx::WriteCheque { user, amount } => bank.get_checking_amount(*user) >= *amount,
+        SmallBankTx::SendPayment { from, amount, .. } => bank.get_checking_amount(*from) >= *amount,
+        SmallBankTx::Split { payors, .. } => payors
+            .iter()
+            .all(|(user, amount)| bank.get_checking_amount(*user) >= *amount),
+        _ => true,
+    }
+}
+
+/// Generate transactions and replay them on a fresh bank, returning how many withdrawals were not
+/// covered by the balances at the time they executed.
+fn uncovered_withdrawals(balance_tracking: BalanceTracking) -> usize {
+    let mut generator =
+        SmallBankTransactionHandler::with_seed(TX_SIZE, N_USERS, 0.5, write_only(), 3);
+    generator.set_balance_tracking(balance_tracking);
+    let mut replica = SmallBankTransactionHandler::new(TX_SIZE, N_USERS, 0.5, write_only());
+
+    let mut uncovered = 0;
+    for uid in 0..2_000 {
+        let bytes = generator.get_next_transaction(false, uid);
+        let tx = replica.decode_transaction(&bytes).unwrap();
+        if !is_covered(&replica.small_bank, &tx) {
+            uncovered += 1;
+        }
+        replica.execute_transaction(bytes).unwrap();
+    }
+    uncovered
+}
+
+#[test]
+fn generated_transactions_track_balances() {
+    assert_eq!(uncovered_withdrawals(BalanceTracking::Generated), 0);
+}
+
+#[test]
+fn stale_balances_produce_uncovered_withdrawals() {
+    assert!(uncovered_withdrawals(BalanceTracking::None) > 0);
+}
+
+#[test]
+fn committed_transactions_are_fed_back() {
+    let mut generator =
+        SmallBankTransactionHandler::with_seed(TX_SIZE, N_USERS, 0.5, write_only(), 3);
+    generator.set_balance_tracking(BalanceTracking::Committed);
+
+    // Generating alone does not change the balances...
+    let tx = SmallBankTx::DepositChecking {
+        user: 1,
+        amount: 10,
+    };
+    let bytes = tx.encode(
+        TxHeader {
+            sample: false,
+            uid: 0,
+        },
+        TX_SIZE,
+    );
+    generator.get_next_transaction(false, 1);
+    assert_eq!(generator.small_bank.get_checking_amount(1), 1000);
+
+    // ... committed transactions do.
+    generator.execute_transaction(bytes).unwrap();
+    assert_eq!(generator.small_bank.get_checking_amount(1), 1010);
+}
+
+/// Generate transactions with the specified mix and cross-shard probability, and return the fraction
+/// of them touching several shards.
+fn cross_shard_ratio(mix: &str, prob_cross_shard: f64) -> f64 {
+    let shard_map = Arc::new(ModuloShardMap::new(4));
+    let mix = mix.parse().unwrap();
+    let mut generator = SmallBankTransactionHandler::with_seed(TX_SIZE, 1_000, 0.5, mix, 5);
+    generator.set_cross_shard_probability(shard_map.clone(), prob_cross_shard);
+
+    let n_transactions = 2_000;
+    let cross_shard = (0..n_transactions)
+        .filter(|uid| {
+            let bytes = generator.get_next_transaction(false, *uid);
+            let (_, users) = generator.get_transaction_dependency(bytes).unwrap();
+            let first = shard_map.shard(users[0] as u64);
+            users
+                .iter()
+                .any(|user| shard_map.shard(*user as u64) != first)
+        })
+        .count();
+    cross_shard as f64 / n_transactions as f64
+}
+
+#[test]
+fn cross_shard_probability_controls_send() {
+    assert_eq!(cross_shard_ratio("send=1", 0.0), 0.0);
+    assert_eq!(cross_shard_ratio("send=1", 1.0), 1.0);
+    let ratio = cross_shard_ratio("send=1", 0.3);
+    assert!(ratio > 0.25 && ratio < 0.35, "{}", ratio);
+}
+
+#[test]
+fn cross_shard_probability_controls_split() {
+    assert_eq!(cross_shard_ratio("split=1", 0.0), 0.0);
+    assert_eq!(cross_shard_ratio("split=1", 1.0), 1.0);
+    let ratio = cross_shard_ratio("split=1", 0.7);
+    assert!(ratio > 0.65 && ratio < 0.75, "{}", ratio);
+}
+
+#[test]
+fn counterparty_falls_back_to_the_only_eligible_users() {
+    // Users 0 and 1 are alone on their shard, so a single-shard send from user 1 goes to user 1.
+    let shard_map = Arc::new(RangeShardMap::new(vec![0, 1, 2]));
+    let mut generator =
+        SmallBankTransactionHandler::with_seed(TX_SIZE, N_USERS, 0.5, write_only(), 1);
+    generator.set_cross_shard_probability(shard_map, 0.0);
+    assert_eq!(generator._sample_counterparty(1, Some(false), &[]), 1);
+    assert_eq!(generator._sample_counterparty(0, Some(false), &[]), 0);
+}
+
+#[test]
+fn counterparty_fallback_skips_excluded_users() {
+    // Shard 1 only holds users 1 and 2: once user 2 is excluded, user 1 is the only choice.
+    let shard_map = Arc::new(RangeShardMap::new(vec![0, 1, 3]));
+    let mut generator =
+        SmallBankTransactionHandler::with_seed(TX_SIZE, N_USERS, 0.5, write_only(), 1);
+    generator.set_cross_shard_probability(shard_map.clone(), 0.0);
+    for _ in 0..100 {
+        assert_eq!(generator._sample_counterparty(2, Some(false), &[2]), 1);
+        let counterparty = generator._sample_counterparty(0, Some(true), &[1, 2]);
+        assert_eq!(shard_map.shard(counterparty as u64), 2);
+    }
+}
+
+fn execute(handler: &mut SmallBankTransactionHandler, tx: SmallBankTx) -> ExecutionOutcome {
+    handler
+        .execute_transaction(tx.encode(
+            TxHeader {
+                sample: false,
+                uid: 0,
+            },
+            TX_SIZE,
+        ))
+        .unwrap()
+}
+
+/// The total amount of money in the bank.
+fn total(handler: &SmallBankTransactionHandler) -> u64 {
+    (0..N_USERS as u32)
+        .map(|user| {
+            handler.small_bank.get_checking_amount(user) as u64
+                + handler.small_bank.get_saving_amount(user) as u64
+        })
+        .sum()
+}
+
+#[test]
+fn failed_transactions_leave_balances_untouched() {
+    let mut handler = SmallBankTransactionHandler::new(TX_SIZE, N_USERS, 0.5, write_only());
+    let before = total(&handler);
+
+    // The payor cannot afford the payment, so the payee gets nothing either.
+    let send = SmallBankTx::SendPayment {
+        from: 1,
+        to: 2,
+        amount: 1_001,
+    };
+    assert_eq!(
+        execute(&mut handler, send),
+        ExecutionOutcome::InsufficientFunds
+    );
+
+    // The second payor cannot afford its share.
+    let split = SmallBankTx::Split {
+        payors: vec![(1, 500), (2, 1_500)],
+        payees: vec![(3, 1_000), (4, 1_000)],
+    };
+    assert_eq!(
+        execute(&mut handler, split),
+        ExecutionOutcome::InsufficientFunds
+    );
+
+    // The deposit would overflow the checking account.
+    let deposit = SmallBankTx::DepositChecking {
+        user: 5,
+        amount: u32::MAX,
+    };
+    assert_eq!(execute(&mut handler, deposit), ExecutionOutcome::Overflow);
+
+    assert_eq!(total(&handler), before);
+    assert_eq!(handler.small_bank.get_checking_amount(1), 1_000);
+    assert_eq!(handler.small_bank.get_checking_amount(3), 1_000);
+}
+
+#[test]
+fn amalgamate_is_all_or_nothing() {
+    let mut handler = SmallBankTransactionHandler::new(TX_SIZE, N_USERS, 0.5, write_only());
+    let deposit = SmallBankTx::DepositChecking {
+        user: 1,
+        amount: u32::MAX - 1_500,
+    };
+    assert_eq!(execute(&mut handler, deposit), ExecutionOutcome::Committed);
+
+    // Moving the 1000 saved would overflow the checking account.
+    let amalgamate = SmallBankTx::Amalgamate { user: 1 };
+    assert_eq!(
+        execute(&mut handler, amalgamate),
+        ExecutionOutcome::Overflow
+    );
+    assert_eq!(handler.small_bank.get_saving_amount(1), 1_000);
+    assert_eq!(handler.small_bank.get_checking_amount(1), u32::MAX - 500);
+
+    // Other users can amalgamate their accounts.
+    let amalgamate = SmallBankTx::Amalgamate { user: 2 };
+    assert_eq!(
+        execute(&mut handler, amalgamate),
+        ExecutionOutcome::Committed
+    );
+    assert_eq!(handler.small_bank.get_saving_amount(2), 0);
+    assert_eq!(handler.small_bank.get_checking_amount(2), 2_000);
+}
+
+#[test]
+fn unknown_accounts_are_reported() {
+    let mut handler = SmallBankTransactionHandler::new(TX_SIZE, N_USERS, 0.5, write_only());
+    let read = SmallBankTx::Read {
+        user: N_USERS as u32,
+    };
+    assert_eq!(handler.execute(&read), ExecutionOutcome::UnknownAccount);
+    let amalgamate = SmallBankTx::Amalgamate {
+        user: N_USERS as u32,
+    };
+    assert_eq!(
+        handler.execute(&amalgamate),
+        ExecutionOutcome::UnknownAccount
+    );
+}
+
+#[test]
+fn transfers_conserve_money() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut handler = SmallBankTransactionHandler::new(TX_SIZE, N_USERS, 0.5, write_only());
+    let before = total(&handler);
+
+    // Random (often unaffordable) transfers never create or destroy money.
+    for _ in 0..2_000 {
+        let user = |rng: &mut StdRng| rng.gen_range(0..N_USERS as u32);
+        let tx = match rng.gen_range(0..3) {
+            0 => SmallBankTx::SendPayment {
+                from: user(&mut rng),
+                to: user(&mut rng),
+                amount: rng.gen_range(0..2_000),
+            },
+            1 => {
+                let amount = rng.gen_range(0..2_000);
+                SmallBankTx::Split {
+                    payors: vec![(user(&mut rng), amount)],
+                    payees: vec![
+                        (user(&mut rng), amount / 2),
+                        (user(&mut rng), amount - amount / 2),
+                    ],
+                }
+            }
+            _ => SmallBankTx::Amalgamate {
+                user: user(&mut rng),
+            },
+        };
+        handler.execute(&tx);
+    }
+    assert_eq!(total(&handler), before);
+}
+
+#[test]
+fn partitioned_bank_only_holds_owned_accounts() {
+    // Modulo and range maps index the owned users arithmetically, the others list them.
+    let shard_maps: Vec<Arc<dyn ShardMap>> = vec![
+        Arc::new(ModuloShardMap::new(4)),
+        Arc::new(RangeShardMap::uniform(N_USERS, 4)),
+        Arc::new(ConsistentHashShardMap::new(4)),
+    ];
+    for shard_map in shard_maps {
+        check_partitioned_bank(shard_map);
+    }
+}
+
+fn check_partitioned_bank(shard_map: Arc<dyn ShardMap>) {
+    let mut banks: Vec<_> = (0..4)
+        .map(|shard_id| SmallBank::partitioned(N_USERS, shard_map.clone(), shard_id))
+        .collect();
+
+    // Every account is held by exactly one shard.
+    assert_eq!(
+        banks.iter().map(SmallBank::len).sum::<usize>(),
+        N_USERS as usize
+    );
+    for user in 0..N_USERS as u32 {
+        let owners: Vec<_> = banks
+            .iter()
+            .filter(|bank| bank.balance(Account::Checking, user).is_ok())
+            .collect();
+        assert_eq!(owners.len(), 1);
+    }
+    let foreign = (0..N_USERS as u32)
+        .find(|user| shard_map.shard(*user as UserId) != 0)
+        .unwrap();
+    assert_eq!(
+        banks[0].balance(Account::Saving, foreign),
+        Err(AccountError::NotOwned)
+    );
+    assert_eq!(
+        banks[0].balance(Account::Saving, N_USERS as u32),
+        Err(AccountError::Unknown)
+    );
+
+    // Every owned user has its own accounts.
+    for user in 0..N_USERS as u32 {
+        let bank = &mut banks[shard_map.shard(user as UserId) as usize];
+        bank.set_balance(Account::Checking, user, user);
+    }
+    for user in 0..N_USERS as u32 {
+        let bank = &banks[shard_map.shard(user as UserId) as usize];
+        assert_eq!(bank.balance(Account::Checking, user), Ok(user));
+    }
+}
+
+#[test]
+fn foreign_accounts_are_not_owned() {
+    let shard_map: Arc<dyn ShardMap> = Arc::new(ModuloShardMap::new(2));
+    let mut handler = SmallBankTransactionHandler::new(TX_SIZE, N_USERS, 0.5, write_only());
+    handler.set_partition(shard_map, 0);
+
+    // Users 0 and 2 live on shard 0, user 1 on shard 1.
+    let local = SmallBankTx::SendPayment {
+        from: 0,
+        to: 2,
+        amount: 10,
+    };
+    assert_eq!(handler.execute(&local), ExecutionOutcome::Committed);
+    let foreign = SmallBankTx::SendPayment {
+        from: 0,
+        to: 1,
+        amount: 10,
+    };
+    assert_eq!(handler.execute(&foreign), ExecutionOutcome::NotOwned);
+    assert_eq!(
+        handler.small_bank.get_checking_amount(0),
+        INITIAL_BALANCE - 10
+    );
+    assert_eq!(
+        handler.small_bank.get_checking_amount(2),
+        INITIAL_BALANCE + 10
+    );
+}
+
+#[test]
+fn balance_queries() {
+    let mut handler = SmallBankTransactionHandler::new(TX_SIZE, N_USERS, 0.5, write_only());
+    handler.execute(&SmallBankTx::DepositSaving { user: 2, amount: 5 });
+    assert_eq!(
+        handler.get_balance(2),
+        Ok((INITIAL_BALANCE, INITIAL_BALANCE + 5))
+    );
+    assert_eq!(
+        handler.get_balance(N_USERS as u32),
+        Err(AccountError::Unknown)
+    );
+
+    handler.set_partition(Arc::new(ModuloShardMap::new(2)), 0);
+    assert_eq!(handler.get_balance(1), Err(AccountError::NotOwned));
+}
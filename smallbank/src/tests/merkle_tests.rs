@@ -0,0 +1,83 @@
+use super::*;
+use crate::{ModuloShardMap, SmallBankTransactionHandler, SmallBankTx, TransactionMix};
+use std::sync::Arc;
+
+fn leaves(n: u32) -> Vec<Digest> {
+    (0..n).map(|user| account_leaf(user, user, 0)).collect()
+}
+
+#[test]
+fn incremental_updates_match_rebuild() {
+    for n in [1, 2, 5, 8, 13] {
+        let mut expected = leaves(n);
+        let mut tree = MerkleTree::new(expected.clone());
+        for index in 0..n as usize {
+            let leaf = account_leaf(index as u32, 7, 7);
+            tree.update(index, leaf);
+            expected[index] = leaf;
+            assert_eq!(tree.root(), MerkleTree::new(expected.clone()).root());
+        }
+    }
+}
+
+#[test]
+fn proofs_verify_against_the_root() {
+    let tree = MerkleTree::new(leaves(13));
+    for index in 0..13 {
+        let mut proof = AccountProof {
+            user_id: index as u32,
+            checking: index as u32,
+            saving: 0,
+            index,
+            siblings: tree.proof(index),
+        };
+        assert!(proof.verify(&tree.root()));
+        proof.checking += 1;
+        assert!(!proof.verify(&tree.root()));
+    }
+}
+
+fn handler() -> SmallBankTransactionHandler {
+    let mix = TransactionMix::preset("write_only").unwrap();
+    let mut handler = SmallBankTransactionHandler::with_seed(64, 20, 0.5, mix, 0);
+    handler.enable_state_commitment();
+    handler
+}
+
+#[test]
+fn replicas_agree_on_the_state_root() {
+    let (mut generator, mut replica, mut diverging) = (handler(), handler(), handler());
+    assert_eq!(replica.state_root(), diverging.state_root());
+
+    for uid in 0..200 {
+        let bytes = generator.get_next_transaction(false, uid);
+        replica.execute_transaction(bytes.clone()).unwrap();
+        if uid != 100 {
+            diverging.execute_transaction(bytes).unwrap();
+        }
+    }
+    assert!(replica.state_root().is_some());
+    assert_ne!(replica.state_root(), diverging.state_root());
+
+    let root = replica.state_root().unwrap();
+    let proof = replica.account_proof(3).unwrap();
+    assert_eq!(proof.checking, replica.small_bank.get_checking_amount(3));
+    assert!(proof.verify(&root));
+}
+
+#[test]
+fn partitioned_state_root() {
+    let mut handler = handler();
+    handler.set_partition(Arc::new(ModuloShardMap::new(2)), 1);
+    let root = handler.state_root().unwrap();
+    assert!(handler.account_proof(0).is_none());
+    assert!(handler.account_proof(1).unwrap().verify(&root));
+
+    let tx = SmallBankTx::DepositChecking { user: 3, amount: 5 };
+    handler.execute(&tx);
+    assert_ne!(handler.state_root().unwrap(), root);
+    assert!(handler
+        .account_proof(3)
+        .unwrap()
+        .verify(&handler.state_root().unwrap()));
+}
@@ -0,0 +1,57 @@
+use super::*;
+use rand::distributions::Distribution as _;
+use rand::rngs::StdRng;
+use rand::SeedableRng as _;
+
+#[test]
+fn parse_weights() {
+    let mix: TransactionMix = "deposit_saving=10, send=40,split=5".parse().unwrap();
+    assert_eq!(mix.weight(0), 10.0);
+    assert_eq!(mix.weight(3), 40.0);
+    assert_eq!(mix.weight(4), 5.0);
+    assert_eq!(mix.weight(6), 0.0);
+}
+
+#[test]
+fn parse_preset() {
+    let mix: TransactionMix = "oltpbench".parse().unwrap();
+    assert_eq!(mix, TransactionMix::preset("oltpbench").unwrap());
+    assert_eq!(mix.weight(4), 0.0);
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(
+        "transfer=10".parse::<TransactionMix>(),
+        Err(MixError::UnknownType("transfer".to_string()))
+    );
+    assert_eq!(
+        "send".parse::<TransactionMix>(),
+        Err(MixError::Malformed("send".to_string()))
+    );
+    assert_eq!("send=0".parse::<TransactionMix>(), Err(MixError::Empty));
+    assert_eq!(
+        "send=-1".parse::<TransactionMix>(),
+        Err(MixError::InvalidWeight(-1.0))
+    );
+}
+
+#[test]
+fn legacy_mix() {
+    let mix = TransactionMix::from_prob_choose_mtx(0.6);
+    assert!((mix.weight(6) - 0.4).abs() < 1e-9);
+    assert!((0..6).all(|tx_type| (mix.weight(tx_type) - 0.1).abs() < 1e-9));
+}
+
+#[test]
+fn sampling_follows_weights() {
+    let mix: TransactionMix = "send=3,split=1".parse().unwrap();
+    let distribution = mix.distribution();
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut counts = [0u32; N_TX_TYPES];
+    for _ in 0..10_000 {
+        counts[distribution.sample(&mut rng)] += 1;
+    }
+    assert_eq!(counts[3] + counts[4], 10_000);
+    assert!(counts[3] > 7_000 && counts[3] < 8_000, "{:?}", counts);
+}
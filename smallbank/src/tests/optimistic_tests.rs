@@ -0,0 +1,68 @@
+use super::*;
+use crate::{ParallelExecutor, SmallBankTransactionHandler, TransactionMix};
+use bytes::Bytes;
+
+const TX_SIZE: usize = 64;
+const N_USERS: u64 = 50;
+
+fn handler(skew_factor: f64, seed: u64) -> SmallBankTransactionHandler {
+    let mix = TransactionMix::preset("uniform").unwrap();
+    let mut handler =
+        SmallBankTransactionHandler::with_seed(TX_SIZE, N_USERS, skew_factor, mix, seed);
+    handler.enable_state_commitment();
+    handler
+}
+
+#[test]
+fn optimistic_execution_matches_serial_execution() {
+    for skew_factor in [0.1, 1.0, 2.0] {
+        let executor = OptimisticExecutor::new(4);
+        let mut generator = handler(skew_factor, 0);
+        let (mut serial, mut optimistic) = (handler(skew_factor, 1), handler(skew_factor, 1));
+        for block in 0..10 {
+            let block: Vec<Bytes> = (0..100)
+                .map(|i| generator.get_next_transaction(false, block * 100 + i))
+                .collect();
+            let expected: Vec<_> = block
+                .iter()
+                .map(|tx| serial.execute_transaction(tx.clone()))
+                .collect();
+            assert_eq!(optimistic.execute_block(&executor, &block), expected);
+        }
+        assert_eq!(optimistic.state_root(), serial.state_root());
+        assert!(executor.executions() >= 1_000);
+    }
+}
+
+#[test]
+fn executors_agree() {
+    let mut generator = handler(1.5, 0);
+    let block: Vec<SmallBankTx> = (0..500)
+        .map(|uid| SmallBankTx::decode(&generator.get_next_transaction(false, uid)).unwrap())
+        .collect();
+    let (mut declared, mut optimistic) = (handler(1.5, 1), handler(1.5, 1));
+    assert_eq!(
+        ParallelExecutor::new(4).execute(&mut declared.small_bank, &block),
+        OptimisticExecutor::new(4).execute(&mut optimistic.small_bank, &block)
+    );
+    assert_eq!(declared.state_root(), optimistic.state_root());
+}
+
+#[test]
+fn conflicting_chain_is_reexecuted() {
+    // Every transaction depends on the previous one, so the optimistic executor must fix up
+    // speculative reads.
+    let block: Vec<SmallBankTx> = (0..50)
+        .map(|i| SmallBankTx::SendPayment {
+            from: i % 2,
+            to: (i + 1) % 2,
+            amount: 600,
+        })
+        .collect();
+    let mut serial = handler(0.5, 0);
+    let expected: Vec<_> = block.iter().map(|tx| serial.execute(tx)).collect();
+    let mut optimistic = handler(0.5, 0);
+    let outcomes = OptimisticExecutor::new(4).execute(&mut optimistic.small_bank, &block);
+    assert_eq!(outcomes, expected);
+    assert_eq!(optimistic.state_root(), serial.state_root());
+}
@@ -0,0 +1,89 @@
+use super::*;
+use std::fs;
+use tokio::time::{sleep, Duration};
+
+const N_USERS: u64 = 10;
+
+/// Open the accounts persisted at `path`.
+async fn open(path: &str, cache: bool) -> PersistentAccounts {
+    let store = Store::new(path).unwrap();
+    PersistentAccounts::new(store, N_USERS, cache).await.unwrap()
+}
+
+#[tokio::test]
+async fn recover_balances_after_restart() {
+    let path = ".db_test_recover_balances_after_restart";
+    let _ = fs::remove_dir_all(path);
+
+    let mut accounts = open(path, false).await;
+    let send = SmallBankTx::SendPayment {
+        from: 1,
+        to: 2,
+        amount: 100,
+    };
+    assert_eq!(
+        accounts.execute(&send).await.unwrap(),
+        ExecutionOutcome::Committed
+    );
+    drop(accounts);
+
+    // Wait for the store to close the database before opening it again.
+    sleep(Duration::from_millis(100)).await;
+
+    for cache in [true, false] {
+        let mut accounts = open(path, cache).await;
+        let checking = |user_id| (Account::Checking, user_id);
+        for ((account, user_id), expected) in [(checking(1), 900), (checking(2), 1100), (checking(3), 1000)] {
+            let balance = accounts.balance(account, user_id).await.unwrap();
+            assert_eq!(balance, Some(expected));
+        }
+        drop(accounts);
+        sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[tokio::test]
+async fn failed_transactions_are_not_persisted() {
+    let path = ".db_test_failed_transactions_are_not_persisted";
+    let _ = fs::remove_dir_all(path);
+    let mut accounts = open(path, true).await;
+
+    let split = SmallBankTx::Split {
+        payors: vec![(1, 500), (2, 1_500)],
+        payees: vec![(3, 2_000)],
+    };
+    assert_eq!(
+        accounts.execute(&split).await.unwrap(),
+        ExecutionOutcome::InsufficientFunds
+    );
+    let balance = accounts.balance(Account::Checking, 1).await.unwrap();
+    assert_eq!(balance, Some(INITIAL_BALANCE));
+
+    let read = SmallBankTx::Read {
+        user: N_USERS as u32,
+    };
+    assert_eq!(
+        accounts.execute(&read).await.unwrap(),
+        ExecutionOutcome::UnknownAccount
+    );
+}
+
+#[tokio::test]
+async fn corrupted_balance_is_a_load_error() {
+    let path = ".db_test_corrupted_balance_is_a_load_error";
+    let _ = fs::remove_dir_all(path);
+    let mut store = Store::new(path).unwrap();
+    store
+        .write(PersistentAccounts::key(Account::Saving, 3), vec![0, 1])
+        .await;
+
+    let result = PersistentAccounts::new(store, N_USERS, true).await;
+    assert!(matches!(
+        result,
+        Err(PersistenceError::CorruptedBalance {
+            account: Account::Saving,
+            user_id: 3,
+            len: 2
+        })
+    ));
+}
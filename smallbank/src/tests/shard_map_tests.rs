@@ -0,0 +1,92 @@
+use super::*;
+
+#[test]
+fn modulo_placement() {
+    let map = ModuloShardMap::new(4);
+    assert_eq!(map.num_shards(), 4);
+    assert_eq!(map.shard(0), 0);
+    assert_eq!(map.shard(6), 2);
+
+    // Large user ids are not truncated before the modulo.
+    assert_eq!(map.shard((1 << 32) + 1), 1);
+}
+
+#[test]
+fn range_placement() {
+    let map = RangeShardMap::new(vec![0, 10, 25]);
+    assert_eq!(map.num_shards(), 3);
+    assert_eq!(map.shard(0), 0);
+    assert_eq!(map.shard(9), 0);
+    assert_eq!(map.shard(10), 1);
+    assert_eq!(map.shard(24), 1);
+    assert_eq!(map.shard(25), 2);
+    assert_eq!(map.shard(1_000), 2);
+}
+
+#[test]
+fn uniform_ranges() {
+    let map = RangeShardMap::uniform(100, 4);
+    assert_eq!(map.shard(24), 0);
+    assert_eq!(map.shard(25), 1);
+    assert_eq!(map.shard(99), 3);
+}
+
+#[test]
+#[should_panic]
+fn ranges_must_be_sorted() {
+    RangeShardMap::new(vec![0, 20, 10]);
+}
+
+#[test]
+fn consistent_hash_placement() {
+    let map = ConsistentHashShardMap::new(4);
+    assert_eq!(map.num_shards(), 4);
+
+    // Every shard gets a reasonable share of the users.
+    let mut counts = [0u32; 4];
+    for user_id in 0..10_000 {
+        counts[map.shard(user_id) as usize] += 1;
+    }
+    assert!(counts.iter().all(|count| *count > 1_000), "{:?}", counts);
+
+    // The placement is deterministic.
+    let other = ConsistentHashShardMap::new(4);
+    assert!((0..1_000).all(|user_id| map.shard(user_id) == other.shard(user_id)));
+}
+
+#[test]
+fn consistent_hash_moves_few_users_when_adding_a_shard() {
+    let before = ConsistentHashShardMap::new(4);
+    let after = ConsistentHashShardMap::new(5);
+
+    // Users either stay on their shard or move to the new one.
+    for user_id in 0..10_000 {
+        let shard = after.shard(user_id);
+        assert!(shard == before.shard(user_id) || shard == 4);
+    }
+}
+
+#[test]
+fn lookup_placement() {
+    let table = vec![(1, 3), (2, 3)].into_iter().collect();
+    let map = LookupShardMap::new(table, 4);
+    assert_eq!(map.shard(1), 3);
+    assert_eq!(map.shard(2), 3);
+
+    // Users missing from the table fall back to modulo placement.
+    assert_eq!(map.shard(5), 1);
+}
+
+#[test]
+fn users_are_indexed_in_their_shard() {
+    let modulo = ModuloShardMap::new(4);
+    assert_eq!(modulo.index_in_shard(2), Some(0));
+    assert_eq!(modulo.index_in_shard(6), Some(1));
+
+    let range = RangeShardMap::new(vec![0, 10, 25]);
+    assert_eq!(range.index_in_shard(9), Some(9));
+    assert_eq!(range.index_in_shard(10), Some(0));
+    assert_eq!(range.index_in_shard(30), Some(5));
+
+    assert_eq!(ConsistentHashShardMap::new(4).index_in_shard(3), None);
+}
@@ -0,0 +1,277 @@
+use super::*;
+use rand::rngs::StdRng;
+use rand::{Rng as _, SeedableRng as _};
+
+const TX_SIZE: usize = 64;
+
+fn mix(prob_choose_mtx: f64) -> crate::TransactionMix {
+    crate::TransactionMix::from_prob_choose_mtx(prob_choose_mtx)
+}
+
+fn header() -> TxHeader {
+    TxHeader {
+        sample: true,
+        uid: 42,
+    }
+}
+
+fn all_transactions() -> Vec<SmallBankTx> {
+    vec![
+        SmallBankTx::DepositSaving { user: 1, amount: 10 },
+        SmallBankTx::DepositChecking { user: 2, amount: 20 },
+        SmallBankTx::WriteCheque { user: 3, amount: 30 },
+        SmallBankTx::SendPayment {
+            from: 4,
+            to: 5,
+            amount: 40,
+        },
+        SmallBankTx::Split {
+            payors: vec![(6, 10)],
+            payees: vec![(7, 5), (8, 5)],
+        },
+        SmallBankTx::Amalgamate { user: 9 },
+        SmallBankTx::Read { user: 10 },
+    ]
+}
+
+#[test]
+fn encode_decode() {
+    for tx in all_transactions() {
+        let bytes = tx.encode(header(), TX_SIZE);
+        assert_eq!(bytes.len(), TX_SIZE);
+        assert_eq!(bytes[TX_TYPE_BYTE], tx.type_id());
+        assert_eq!(SmallBankTx::decode(&bytes), Ok(tx));
+        assert_eq!(TxHeader::decode(&bytes), Ok(header()));
+    }
+}
+
+#[test]
+fn wire_layout() {
+    let tx = SmallBankTx::SendPayment {
+        from: 1,
+        to: 2,
+        amount: 3,
+    };
+    let bytes = tx.encode(header(), TX_SIZE);
+    assert_eq!(bytes[0], 0);
+    assert_eq!(&bytes[1..9], &42u64.to_be_bytes());
+    assert_eq!(bytes[9], 3);
+    assert_eq!(&bytes[TX_DATA_BYTE..TX_DATA_BYTE + 4], &1u32.to_be_bytes());
+    assert_eq!(&bytes[TX_DATA_BYTE + 4..TX_DATA_BYTE + 8], &2u32.to_be_bytes());
+    assert_eq!(&bytes[TX_DATA_BYTE + 8..TX_DATA_BYTE + 12], &3u32.to_be_bytes());
+}
+
+#[test]
+fn dependency() {
+    let split = SmallBankTx::Split {
+        payors: vec![(6, 10)],
+        payees: vec![(7, 5), (8, 5)],
+    };
+    assert_eq!(split.dependency(), ('w', vec![6, 7, 8]));
+    assert_eq!(SmallBankTx::Read { user: 10 }.dependency(), ('r', vec![10]));
+}
+
+#[test]
+fn decode_rejects_short_transaction() {
+    let bytes = SmallBankTx::Read { user: 10 }.encode(header(), TX_SIZE);
+    let bytes = bytes.slice(..TX_DATA_BYTE + 2);
+    assert_eq!(
+        SmallBankTx::decode(&bytes),
+        Err(DecodeError::TooShort {
+            expected: TX_DATA_BYTE + 4,
+            actual: TX_DATA_BYTE + 2
+        })
+    );
+}
+
+#[test]
+fn decode_rejects_unknown_type() {
+    let mut bytes = SmallBankTx::Read { user: 10 }.encode(header(), TX_SIZE).to_vec();
+    bytes[TX_TYPE_BYTE] = 7;
+    assert_eq!(
+        SmallBankTx::decode(&Bytes::from(bytes)),
+        Err(DecodeError::UnknownType(7))
+    );
+}
+
+#[test]
+fn generated_transactions_decode() {
+    let mut handler = crate::SmallBankTransactionHandler::new(TX_SIZE, 100, 0.5, mix(0.9));
+    for uid in 0..200 {
+        let bytes = handler.get_next_transaction(false, uid);
+        assert_eq!(TxHeader::decode(&bytes).unwrap().uid, uid);
+        assert!(SmallBankTx::decode(&bytes).is_ok());
+    }
+}
+
+#[test]
+fn decode_rejects_invalid_split_party() {
+    for (n_payors, n_payees) in [(0, 2), (2, 0), (3, 3), (u32::MAX, 2)] {
+        let mut bytes = SmallBankTx::Read { user: 10 }.encode(header(), TX_SIZE).to_vec();
+        bytes[TX_TYPE_BYTE] = 4;
+        bytes[TX_DATA_BYTE..TX_DATA_BYTE + 4].copy_from_slice(&u32::to_be_bytes(n_payors));
+        bytes[TX_DATA_BYTE + 4..TX_DATA_BYTE + 8].copy_from_slice(&u32::to_be_bytes(n_payees));
+        assert_eq!(
+            SmallBankTx::decode(&Bytes::from(bytes)),
+            Err(DecodeError::SplitPartyOutOfBounds { n_payors, n_payees })
+        );
+    }
+}
+
+#[test]
+fn handler_validates_transactions() {
+    let mut handler = crate::SmallBankTransactionHandler::new(TX_SIZE, 100, 0.5, mix(0.9));
+
+    let oversized = SmallBankTx::Read { user: 10 }.encode(header(), TX_SIZE + 1);
+    assert_eq!(
+        handler.execute_transaction(oversized),
+        Err(DecodeError::PayloadOverflow {
+            size: TX_SIZE + 1,
+            tx_size: TX_SIZE
+        })
+    );
+
+    let unknown_user = SmallBankTx::Amalgamate { user: 100 }.encode(header(), TX_SIZE);
+    assert_eq!(
+        handler.get_transaction_dependency(unknown_user),
+        Err(DecodeError::UnknownUser {
+            user: 100,
+            n_users: 100
+        })
+    );
+
+    let truncated = Bytes::from_static(&[0, 0, 0]);
+    assert!(handler.get_transaction_uid(truncated.clone()).is_err());
+    assert!(handler.execute_transaction(truncated).is_err());
+}
+
+#[test]
+fn decode_rejects_unbalanced_split() {
+    let split = SmallBankTx::Split {
+        payors: vec![(6, 10)],
+        payees: vec![(7, 5), (8, 4)],
+    };
+    assert_eq!(
+        SmallBankTx::decode(&split.encode(header(), TX_SIZE)),
+        Err(DecodeError::UnbalancedSplit {
+            paid: 10,
+            received: 9
+        })
+    );
+}
+
+#[test]
+fn largest_transaction_fits_max_tx_len() {
+    let split = SmallBankTx::Split {
+        payors: vec![(1, 10), (2, 20)],
+        payees: vec![(3, 10), (4, 10), (5, 10)],
+    };
+    let bytes = split.encode(header(), MAX_TX_LEN);
+    assert_eq!(SmallBankTx::decode(&bytes), Ok(split));
+}
+
+#[test]
+#[should_panic(expected = "exceeds the transaction size")]
+fn encode_rejects_too_small_size() {
+    let split = SmallBankTx::Split {
+        payors: vec![(1, 10), (2, 20)],
+        payees: vec![(3, 10), (4, 10), (5, 10)],
+    };
+    split.encode(header(), MAX_TX_LEN - 1);
+}
+
+#[test]
+fn random_transactions_round_trip() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..1_000 {
+        let user = |rng: &mut StdRng| rng.gen_range(0..1_000);
+        let tx = match rng.gen_range(0..7) {
+            0 => SmallBankTx::DepositSaving {
+                user: user(&mut rng),
+                amount: rng.gen(),
+            },
+            1 => SmallBankTx::DepositChecking {
+                user: user(&mut rng),
+                amount: rng.gen(),
+            },
+            2 => SmallBankTx::WriteCheque {
+                user: user(&mut rng),
+                amount: rng.gen(),
+            },
+            3 => SmallBankTx::SendPayment {
+                from: user(&mut rng),
+                to: user(&mut rng),
+                amount: rng.gen(),
+            },
+            4 => {
+                let n_payors = rng.gen_range(1..SPLIT_PARTY_SIZE_MAX);
+                let n_payees = rng.gen_range(1..=SPLIT_PARTY_SIZE_MAX - n_payors);
+                let payors: Vec<_> = (0..n_payors)
+                    .map(|_| (user(&mut rng), rng.gen_range(0..1_000)))
+                    .collect();
+                let total: u32 = payors.iter().map(|(_, amount)| amount).sum();
+                let mut payees: Vec<_> = (0..n_payees).map(|_| (user(&mut rng), 0)).collect();
+                payees[0].1 = total;
+                SmallBankTx::Split { payors, payees }
+            }
+            5 => SmallBankTx::Amalgamate {
+                user: user(&mut rng),
+            },
+            _ => SmallBankTx::Read {
+                user: user(&mut rng),
+            },
+        };
+        let header = TxHeader {
+            sample: rng.gen(),
+            uid: rng.gen(),
+        };
+        let bytes = tx.encode(header, TX_SIZE);
+        assert_eq!(TxHeader::decode(&bytes), Ok(header));
+        assert_eq!(SmallBankTx::decode(&bytes), Ok(tx));
+    }
+}
+
+#[test]
+fn generated_splits_are_balanced() {
+    let mut handler = crate::SmallBankTransactionHandler::new(TX_SIZE, 100, 0.5, mix(0.9));
+    let total_checking = |handler: &crate::SmallBankTransactionHandler| -> u64 {
+        (0..100)
+            .map(|user| handler.small_bank.get_checking_amount(user) as u64)
+            .sum()
+    };
+    let before = total_checking(&handler);
+
+    for uid in 0..500 {
+        let bytes = handler._generate_transaction(4, false, uid);
+        let tx = SmallBankTx::decode(&bytes).unwrap();
+        match &tx {
+            SmallBankTx::Split { payors, payees } => {
+                // Every user takes part at most once.
+                let (_, mut users) = tx.dependency();
+                users.sort();
+                users.dedup();
+                assert_eq!(users.len(), payors.len() + payees.len());
+            }
+            _ => panic!("Expected a split transaction"),
+        }
+        handler.execute_transaction(bytes).unwrap();
+    }
+
+    // Splits only move money between checking accounts.
+    assert_eq!(total_checking(&handler), before);
+}
+
+#[test]
+fn seeded_generation_is_reproducible() {
+    let handler =
+        |seed| crate::SmallBankTransactionHandler::with_seed(TX_SIZE, 100, 0.5, mix(0.9), seed);
+    let (mut first, mut second, mut other) = (handler(7), handler(7), handler(8));
+    let stream = |handler: &mut crate::SmallBankTransactionHandler| -> Vec<Bytes> {
+        (0..100)
+            .map(|uid| handler.get_next_transaction(false, uid))
+            .collect()
+    };
+    let expected = stream(&mut first);
+    assert_eq!(stream(&mut second), expected);
+    assert_ne!(stream(&mut other), expected);
+}
@@ -0,0 +1,284 @@
+use bytes::BufMut as _;
+use bytes::{Bytes, BytesMut};
+use std::fmt;
+
+#[cfg(test)]
+#[path = "tests/transaction_tests.rs"]
+pub mod transaction_tests;
+
+/// Offset of the transaction type in the serialized transaction.
+pub const TX_TYPE_BYTE: usize = 9;
+/// Offset of the transaction data in the serialized transaction.
+pub const TX_DATA_BYTE: usize = 10;
+/// The maximum number of users (payors and payees) taking part in a split transaction.
+pub const SPLIT_PARTY_SIZE_MAX: u32 = 5;
+/// The maximum length of a serialized transaction (a split among `SPLIT_PARTY_SIZE_MAX` users): the
+/// smallest transaction size fitting every transaction.
+pub const MAX_TX_LEN: usize = TX_DATA_BYTE + 8 + 8 * SPLIT_PARTY_SIZE_MAX as usize;
+
+/// Errors raised when decoding a serialized transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The transaction ends before the end of its fields.
+    TooShort { expected: usize, actual: usize },
+    /// The transaction type is not a SmallBank transaction.
+    UnknownType(u8),
+    /// A split transaction without payor or payee, or with more than `SPLIT_PARTY_SIZE_MAX` users.
+    SplitPartyOutOfBounds { n_payors: u32, n_payees: u32 },
+    /// The transaction is larger than the configured transaction size.
+    PayloadOverflow { size: usize, tx_size: usize },
+    /// The payees of a split transaction do not receive exactly what the payors pay.
+    UnbalancedSplit { paid: u64, received: u64 },
+    /// The transaction touches a user that does not exist.
+    UnknownUser { user: u32, n_users: u64 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::TooShort { expected, actual } => write!(
+                f,
+                "Transaction too short: expected at least {} bytes, got {}",
+                expected, actual
+            ),
+            DecodeError::UnknownType(id) => write!(f, "Unknown transaction type {}", id),
+            DecodeError::SplitPartyOutOfBounds { n_payors, n_payees } => write!(
+                f,
+                "Invalid split party: {} payors and {} payees",
+                n_payors, n_payees
+            ),
+            DecodeError::PayloadOverflow { size, tx_size } => write!(
+                f,
+                "Transaction of {} bytes exceeds the transaction size ({} bytes)",
+                size, tx_size
+            ),
+            DecodeError::UnbalancedSplit { paid, received } => write!(
+                f,
+                "Unbalanced split: payors pay {} but payees receive {}",
+                paid, received
+            ),
+            DecodeError::UnknownUser { user, n_users } => {
+                write!(f, "Unknown user {} (there are {} users)", user, n_users)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The header of every serialized transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxHeader {
+    /// Sample transactions are tracked by the benchmark to measure latency.
+    pub sample: bool,
+    /// Uniquely identifies the transaction.
+    pub uid: u64,
+}
+
+impl TxHeader {
+    pub fn decode(tx: &Bytes) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(tx, 0);
+        let sample = reader.u8()? == 0;
+        let uid = reader.u64()?;
+        Ok(Self { sample, uid })
+    }
+}
+
+/// A SmallBank transaction. Its serialized form is the `TxHeader` (sample flag on 1 byte and uid on
+/// 8 bytes), the transaction type on 1 byte, then the big-endian fields of the transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmallBankTx {
+    /// Type 0: deposit `amount` on the saving account of `user`.
+    DepositSaving { user: u32, amount: u32 },
+    /// Type 1: deposit `amount` on the checking account of `user`.
+    DepositChecking { user: u32, amount: u32 },
+    /// Type 2: withdraw `amount` from the checking account of `user`.
+    WriteCheque { user: u32, amount: u32 },
+    /// Type 3: move `amount` from the checking account of `from` to the one of `to`.
+    SendPayment { from: u32, to: u32, amount: u32 },
+    /// Type 4: withdraw from the checking accounts of the payors and deposit on the ones of the
+    /// payees. Both lists hold (user, amount) pairs, and the payees receive exactly the total paid by
+    /// the payors. It is serialized as the number of payors, the number of payees, the payor pairs,
+    /// then the payee pairs.
+    Split {
+        payors: Vec<(u32, u32)>,
+        payees: Vec<(u32, u32)>,
+    },
+    /// Type 5: move the whole saving account of `user` to its checking account.
+    Amalgamate { user: u32 },
+    /// Type 6: read the checking account of `user`.
+    Read { user: u32 },
+}
+
+impl SmallBankTx {
+    /// The type of the transaction, as serialized.
+    pub fn type_id(&self) -> u8 {
+        match self {
+            SmallBankTx::DepositSaving { .. } => 0,
+            SmallBankTx::DepositChecking { .. } => 1,
+            SmallBankTx::WriteCheque { .. } => 2,
+            SmallBankTx::SendPayment { .. } => 3,
+            SmallBankTx::Split { .. } => 4,
+            SmallBankTx::Amalgamate { .. } => 5,
+            SmallBankTx::Read { .. } => 6,
+        }
+    }
+
+    /// Serialize the transaction and pad it with zeros to `size` bytes. Panics if the transaction does
+    /// not fit in `size` bytes (which never happens from `MAX_TX_LEN` bytes).
+    pub fn encode(&self, header: TxHeader, size: usize) -> Bytes {
+        let mut tx = BytesMut::with_capacity(size);
+        tx.put_u8(if header.sample { 0u8 } else { 1u8 });
+        tx.put_u64(header.uid);
+        tx.put_u8(self.type_id());
+
+        match self {
+            SmallBankTx::DepositSaving { user, amount }
+            | SmallBankTx::DepositChecking { user, amount }
+            | SmallBankTx::WriteCheque { user, amount } => {
+                tx.put_u32(*user);
+                tx.put_u32(*amount);
+            }
+            SmallBankTx::SendPayment { from, to, amount } => {
+                tx.put_u32(*from);
+                tx.put_u32(*to);
+                tx.put_u32(*amount);
+            }
+            SmallBankTx::Split { payors, payees } => {
+                tx.put_u32(payors.len() as u32);
+                tx.put_u32(payees.len() as u32);
+                for (user, amount) in payors.iter().chain(payees.iter()) {
+                    tx.put_u32(*user);
+                    tx.put_u32(*amount);
+                }
+            }
+            SmallBankTx::Amalgamate { user } | SmallBankTx::Read { user } => {
+                tx.put_u32(*user);
+            }
+        }
+
+        assert!(
+            tx.len() <= size,
+            "Transaction of {} bytes exceeds the transaction size ({} bytes)",
+            tx.len(),
+            size
+        );
+        tx.resize(size, 0u8);
+        tx.freeze()
+    }
+
+    /// Deserialize a transaction (its header is ignored, see `TxHeader::decode`).
+    pub fn decode(tx: &Bytes) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(tx, TX_TYPE_BYTE);
+        let decoded = match reader.u8()? {
+            0 => SmallBankTx::DepositSaving {
+                user: reader.u32()?,
+                amount: reader.u32()?,
+            },
+            1 => SmallBankTx::DepositChecking {
+                user: reader.u32()?,
+                amount: reader.u32()?,
+            },
+            2 => SmallBankTx::WriteCheque {
+                user: reader.u32()?,
+                amount: reader.u32()?,
+            },
+            3 => SmallBankTx::SendPayment {
+                from: reader.u32()?,
+                to: reader.u32()?,
+                amount: reader.u32()?,
+            },
+            4 => {
+                let n_payors = reader.u32()?;
+                let n_payees = reader.u32()?;
+                let party_size = n_payors.checked_add(n_payees);
+                if n_payors == 0
+                    || n_payees == 0
+                    || party_size.map_or(true, |x| x > SPLIT_PARTY_SIZE_MAX)
+                {
+                    return Err(DecodeError::SplitPartyOutOfBounds { n_payors, n_payees });
+                }
+                let payors: Vec<_> = (0..n_payors)
+                    .map(|_| Ok((reader.u32()?, reader.u32()?)))
+                    .collect::<Result<_, DecodeError>>()?;
+                let payees: Vec<_> = (0..n_payees)
+                    .map(|_| Ok((reader.u32()?, reader.u32()?)))
+                    .collect::<Result<_, DecodeError>>()?;
+                let paid = Self::total(&payors);
+                let received = Self::total(&payees);
+                if paid != received {
+                    return Err(DecodeError::UnbalancedSplit { paid, received });
+                }
+                SmallBankTx::Split { payors, payees }
+            }
+            5 => SmallBankTx::Amalgamate {
+                user: reader.u32()?,
+            },
+            6 => SmallBankTx::Read {
+                user: reader.u32()?,
+            },
+            id => return Err(DecodeError::UnknownType(id)),
+        };
+        Ok(decoded)
+    }
+
+    /// The sum of the amounts of a list of (user, amount) pairs.
+    fn total(parties: &[(u32, u32)]) -> u64 {
+        parties.iter().map(|(_, amount)| *amount as u64).sum()
+    }
+
+    /// The access type of the transaction (`'r'` for reads and `'w'` for writes) and the users it
+    /// touches.
+    pub fn dependency(&self) -> (char, Vec<u32>) {
+        match self {
+            SmallBankTx::DepositSaving { user, .. }
+            | SmallBankTx::DepositChecking { user, .. }
+            | SmallBankTx::WriteCheque { user, .. }
+            | SmallBankTx::Amalgamate { user } => ('w', vec![*user]),
+            SmallBankTx::SendPayment { from, to, .. } => ('w', vec![*from, *to]),
+            SmallBankTx::Split { payors, payees } => (
+                'w',
+                payors
+                    .iter()
+                    .chain(payees.iter())
+                    .map(|(user, _)| *user)
+                    .collect(),
+            ),
+            SmallBankTx::Read { user } => ('r', vec![*user]),
+        }
+    }
+}
+
+/// Reads big-endian integers out of a serialized transaction.
+struct Reader<'a> {
+    tx: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(tx: &'a [u8], offset: usize) -> Self {
+        Self { tx, offset }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let end = self.offset + N;
+        let bytes = self.tx.get(self.offset..end).ok_or(DecodeError::TooShort {
+            expected: end,
+            actual: self.tx.len(),
+        })?;
+        self.offset = end;
+        Ok(bytes.try_into().expect("Slice has the requested length"))
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_be_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_be_bytes(self.take()?))
+    }
+}
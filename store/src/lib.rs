@@ -15,6 +15,7 @@ type Value = Vec<u8>;
 
 pub enum StoreCommand {
     Write(Key, Value),
+    WriteBatch(Vec<(Key, Value)>, oneshot::Sender<StoreResult<()>>),
     Read(Key, oneshot::Sender<StoreResult<Option<Value>>>),
     NotifyRead(Key, oneshot::Sender<StoreResult<Value>>),
 }
@@ -40,6 +41,23 @@ impl Store {
                             }
                         }
                     }
+                    StoreCommand::WriteBatch(entries, sender) => {
+                        let mut batch = rocksdb::WriteBatch::default();
+                        for (key, value) in &entries {
+                            batch.put(key, value);
+                        }
+                        let response = db.write(batch);
+                        if response.is_ok() {
+                            for (key, value) in entries {
+                                if let Some(mut senders) = obligations.remove(&key) {
+                                    while let Some(s) = senders.pop_front() {
+                                        let _ = s.send(Ok(value.clone()));
+                                    }
+                                }
+                            }
+                        }
+                        let _ = sender.send(response);
+                    }
                     StoreCommand::Read(key, sender) => {
                         let response = db.get(&key);
                         let _ = sender.send(response);
@@ -68,6 +86,22 @@ impl Store {
         }
     }
 
+    /// Atomically write several values: either all of them or none of them are persisted. Returns
+    /// once the values are written.
+    pub async fn write_batch(&mut self, entries: Vec<(Key, Value)>) -> StoreResult<()> {
+        let (sender, receiver) = oneshot::channel();
+        if let Err(e) = self
+            .channel
+            .send(StoreCommand::WriteBatch(entries, sender))
+            .await
+        {
+            panic!("Failed to send WriteBatch command to store: {}", e);
+        }
+        receiver
+            .await
+            .expect("Failed to receive reply to WriteBatch command from store")
+    }
+
     pub async fn read(&mut self, key: Key) -> StoreResult<Option<Value>> {
         let (sender, receiver) = oneshot::channel();
         if let Err(e) = self.channel.send(StoreCommand::Read(key, sender)).await {
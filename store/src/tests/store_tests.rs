@@ -31,6 +31,25 @@ async fn read_write_value() {
     assert_eq!(read_value.unwrap(), value);
 }
 
+#[tokio::test]
+async fn write_batch() {
+    // Create new store.
+    let path = ".db_test_write_batch";
+    let _ = fs::remove_dir_all(path);
+    let mut store = Store::new(path).unwrap();
+
+    // Write two values in a single batch.
+    let entries = vec![(vec![0u8, 1u8], vec![2u8, 3u8]), (vec![4u8, 5u8], vec![6u8, 7u8])];
+    assert!(store.write_batch(entries.clone()).await.is_ok());
+
+    // Read both values.
+    for (key, value) in entries {
+        let result = store.read(key).await;
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Some(value));
+    }
+}
+
 #[tokio::test]
 async fn read_unknown_key() {
     // Create new store.